@@ -0,0 +1,154 @@
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+};
+
+use flate2::{read::GzDecoder, write::GzEncoder};
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+/// Object metadata naming the algorithm an object was compressed with.
+pub const COMPRESSION_KEY: &str = "cync-compression";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Algorithm {
+    #[default]
+    Zstd,
+    Gzip,
+}
+
+/// The `[compression]` section of the config file. Extensions are matched without the dot and
+/// case-insensitively; an empty `include` list means every extension not in `exclude`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompressionConfig {
+    pub algorithm: Algorithm,
+    pub level: Option<u32>,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        CompressionConfig {
+            algorithm: Algorithm::default(),
+            level: None,
+            include: Vec::new(),
+            // Formats that are compressed already and would only grow.
+            exclude: [
+                "7z", "avif", "br", "bz2", "docx", "gif", "gz", "heic", "jpeg", "jpg", "mkv",
+                "mov", "mp3", "mp4", "ogg", "pdf", "png", "pptx", "rar", "webm", "webp", "xlsx",
+                "xz", "zip", "zst",
+            ]
+            .map(String::from)
+            .to_vec(),
+        }
+    }
+}
+
+impl CompressionConfig {
+    fn applies_to(&self, path: &str) -> bool {
+        let file_name = path.rsplit('/').next().unwrap_or(path);
+        let extension = file_name
+            .rsplit_once('.')
+            .map(|(_, extension)| extension)
+            .unwrap_or_default();
+        let listed = |list: &[String]| list.iter().any(|e| e.eq_ignore_ascii_case(extension));
+        (self.include.is_empty() || listed(&self.include)) && !listed(&self.exclude)
+    }
+
+    /// Compresses `contents` if `path` is covered and it actually gets smaller, recording the
+    /// algorithm in `metadata`.
+    pub fn compress(
+        &self,
+        path: &str,
+        contents: Vec<u8>,
+        metadata: &mut HashMap<String, String>,
+    ) -> Vec<u8> {
+        if !self.applies_to(path) {
+            return contents;
+        }
+        let compressed = match self.algorithm {
+            Algorithm::Zstd => {
+                let level = self
+                    .level
+                    .map_or(zstd::DEFAULT_COMPRESSION_LEVEL, |l| l as i32);
+                zstd::encode_all(contents.as_slice(), level)
+            }
+            Algorithm::Gzip => {
+                let level = self.level.map_or(flate2::Compression::default(), |level| {
+                    flate2::Compression::new(level.min(9))
+                });
+                let mut encoder = GzEncoder::new(Vec::new(), level);
+                encoder.write_all(&contents).and_then(|_| encoder.finish())
+            }
+        };
+        match compressed {
+            Ok(compressed) if compressed.len() < contents.len() => {
+                let name = match self.algorithm {
+                    Algorithm::Zstd => "zstd",
+                    Algorithm::Gzip => "gzip",
+                };
+                metadata.insert(COMPRESSION_KEY.to_string(), name.to_string());
+                compressed
+            }
+            _ => contents,
+        }
+    }
+}
+
+/// Reverses `compress` for objects carrying the marker, whatever the current config says.
+pub fn decompress(
+    path: &str,
+    object: Vec<u8>,
+    metadata: &HashMap<String, String>,
+) -> Result<Vec<u8>, Error> {
+    let decompressed = match metadata.get(COMPRESSION_KEY).map(String::as_str) {
+        None => return Ok(object),
+        Some("zstd") => zstd::decode_all(object.as_slice()),
+        Some("gzip") => {
+            let mut contents = Vec::new();
+            GzDecoder::new(object.as_slice())
+                .read_to_end(&mut contents)
+                .map(|_| contents)
+        }
+        Some(other) => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("unknown compression `{other}`"),
+        )),
+    };
+    decompressed.map_err(|source| Error::Decompression {
+        path: path.to_string(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compression_round_trip_and_exclusions() {
+        let contents = b"timestamp,level,message\n".repeat(200);
+        for algorithm in [Algorithm::Zstd, Algorithm::Gzip] {
+            let config = CompressionConfig {
+                algorithm,
+                ..CompressionConfig::default()
+            };
+            let mut metadata = HashMap::new();
+            let object = config.compress("logs/app.csv", contents.clone(), &mut metadata);
+            assert!(object.len() < contents.len());
+            assert_eq!(
+                decompress("logs/app.csv", object, &metadata).unwrap(),
+                contents
+            );
+
+            let mut metadata = HashMap::new();
+            let object = config.compress("photos/beach.JPG", contents.clone(), &mut metadata);
+            assert_eq!(object, contents);
+            assert!(metadata.is_empty());
+        }
+    }
+}
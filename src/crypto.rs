@@ -0,0 +1,231 @@
+use std::{collections::HashMap, fs, io, path::PathBuf};
+
+use aes_siv::siv::Aes256Siv;
+use argon2::Argon2;
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    XChaCha20Poly1305, XNonce,
+};
+use data_encoding::BASE32HEX_NOPAD;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::error::Error;
+
+// Every encrypted object starts with this, followed by the nonce and the sealed contents.
+const MAGIC: &[u8] = b"CYNC\x01";
+const NONCE_LEN: usize = 24;
+// Keys are derived from the secret alone so every machine sharing it ends up with the same key.
+const KDF_SALT: &[u8] = b"cync client-side encryption";
+const NAME_KDF_SALT: &[u8] = b"cync client-side name encryption";
+
+/// Object metadata naming the algorithm an object was encrypted with.
+pub const ENCRYPTION_KEY: &str = "cync-encryption";
+const ALGORITHM: &str = "xchacha20poly1305";
+
+/// The `[encryption]` section of the config file. Exactly one of `passphrase` and `keyfile`
+/// must be set.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct EncryptionConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub passphrase: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keyfile: Option<PathBuf>,
+    /// Also encrypt file and folder names, so object keys reveal nothing but the nesting.
+    #[serde(default)]
+    pub names: bool,
+}
+
+#[derive(Error, Debug)]
+pub enum KeyError {
+    #[error("Set either `passphrase` or `keyfile` in the `[encryption]` section")]
+    NoSecret,
+
+    #[error("Set only one of `passphrase` and `keyfile` in the `[encryption]` section")]
+    TwoSecrets,
+
+    #[error("Could not read keyfile at path: `{}`", path.display())]
+    Keyfile { path: PathBuf, source: io::Error },
+
+    #[error("Could not derive a key from the secret: {0}")]
+    Derivation(argon2::Error),
+}
+
+/// Authenticated encryption of file contents with XChaCha20-Poly1305, and optionally of names
+/// with AES-SIV.
+pub struct Cipher {
+    aead: XChaCha20Poly1305,
+    names: Option<[u8; 64]>,
+}
+
+impl Cipher {
+    /// Derives the key from the passphrase or the keyfile contents with Argon2id.
+    pub fn new(config: &EncryptionConfig) -> Result<Self, KeyError> {
+        let secret = match (&config.passphrase, &config.keyfile) {
+            (Some(passphrase), None) => passphrase.as_bytes().to_vec(),
+            (None, Some(path)) => fs::read(path).map_err(|source| KeyError::Keyfile {
+                path: path.clone(),
+                source,
+            })?,
+            (None, None) => return Err(KeyError::NoSecret),
+            (Some(_), Some(_)) => return Err(KeyError::TwoSecrets),
+        };
+        let mut key = [0; 32];
+        Argon2::default()
+            .hash_password_into(&secret, KDF_SALT, &mut key)
+            .map_err(KeyError::Derivation)?;
+        let names = if config.names {
+            let mut key = [0; 64];
+            Argon2::default()
+                .hash_password_into(&secret, NAME_KDF_SALT, &mut key)
+                .map_err(KeyError::Derivation)?;
+            Some(key)
+        } else {
+            None
+        };
+        Ok(Cipher {
+            aead: XChaCha20Poly1305::new(&key.into()),
+            names,
+        })
+    }
+
+    /// Encrypts every segment of `path` on its own, so folders stay folders on the remote.
+    /// The same path always gives the same key, which is what lets it be looked up again.
+    pub fn encrypt_name(&self, path: &str) -> String {
+        let Some(key) = &self.names else {
+            return path.to_string();
+        };
+        let mut siv = Aes256Siv::new(key.into());
+        path.split('/')
+            .map(|segment| {
+                let sealed = siv
+                    .encrypt([&[] as &[u8]], segment.as_bytes())
+                    .expect("encrypting into memory does not fail");
+                BASE32HEX_NOPAD.encode(&sealed).to_lowercase()
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// Reverses `encrypt_name`, or returns `None` for keys that were not encrypted with this key.
+    pub fn decrypt_name(&self, key: &str) -> Option<String> {
+        let Some(name_key) = &self.names else {
+            return Some(key.to_string());
+        };
+        let mut siv = Aes256Siv::new(name_key.into());
+        key.split('/')
+            .map(|segment| {
+                let sealed = BASE32HEX_NOPAD
+                    .decode(segment.to_uppercase().as_bytes())
+                    .ok()?;
+                let plain = siv.decrypt([&[] as &[u8]], &sealed).ok()?;
+                String::from_utf8(plain).ok()
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(|segments| segments.join("/"))
+    }
+
+    fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let sealed = self
+            .aead
+            .encrypt(&nonce, plaintext)
+            .expect("encrypting into memory does not fail");
+        [MAGIC, nonce.as_slice(), &sealed].concat()
+    }
+
+    fn decrypt(&self, path: &str, object: &[u8]) -> Result<Vec<u8>, Error> {
+        let failed = || Error::Decryption {
+            path: path.to_string(),
+        };
+        let rest = object.strip_prefix(MAGIC).ok_or_else(failed)?;
+        if rest.len() < NONCE_LEN {
+            return Err(failed());
+        }
+        let (nonce, sealed) = rest.split_at(NONCE_LEN);
+        self.aead
+            .decrypt(XNonce::from_slice(nonce), sealed)
+            .map_err(|_| failed())
+    }
+}
+
+/// Encrypts `contents` when there is a cipher, marking it in `metadata` for `open`.
+pub fn seal(
+    cipher: Option<&Cipher>,
+    contents: Vec<u8>,
+    metadata: &mut HashMap<String, String>,
+) -> Vec<u8> {
+    match cipher {
+        Some(cipher) => {
+            metadata.insert(ENCRYPTION_KEY.to_string(), ALGORITHM.to_string());
+            cipher.encrypt(&contents)
+        }
+        None => contents,
+    }
+}
+
+/// Reverses `seal`. Objects uploaded without encryption are passed through, so a bucket can be
+/// switched over gradually.
+pub fn open(
+    cipher: Option<&Cipher>,
+    path: &str,
+    object: Vec<u8>,
+    metadata: &HashMap<String, String>,
+) -> Result<Vec<u8>, Error> {
+    if !metadata.contains_key(ENCRYPTION_KEY) {
+        return Ok(object);
+    }
+    let cipher = cipher.ok_or_else(|| Error::MissingEncryptionKey {
+        path: path.to_string(),
+    })?;
+    cipher.decrypt(path, &object)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sealed_contents_round_trip() {
+        let config = EncryptionConfig {
+            passphrase: Some(String::from("correct horse battery staple")),
+            ..EncryptionConfig::default()
+        };
+        let cipher = Cipher::new(&config).unwrap();
+        let mut metadata = HashMap::new();
+        let object = seal(Some(&cipher), b"secret notes".to_vec(), &mut metadata);
+        assert!(!object
+            .windows(b"secret".len())
+            .any(|window| window == b"secret"));
+
+        let contents = open(Some(&cipher), "notes.txt", object.clone(), &metadata).unwrap();
+        assert_eq!(contents, b"secret notes");
+
+        let other = Cipher::new(&EncryptionConfig {
+            passphrase: Some(String::from("wrong")),
+            ..EncryptionConfig::default()
+        })
+        .unwrap();
+        assert!(open(Some(&other), "notes.txt", object.clone(), &metadata).is_err());
+        assert!(open(None, "notes.txt", object, &metadata).is_err());
+    }
+
+    #[test]
+    fn test_names_are_encrypted_per_segment() {
+        let cipher = Cipher::new(&EncryptionConfig {
+            keyfile: None,
+            passphrase: Some(String::from("correct horse battery staple")),
+            names: true,
+        })
+        .unwrap();
+        let key = cipher.encrypt_name("photos/2024/beach.jpg");
+        assert_eq!(key.split('/').count(), 3);
+        assert!(!key.contains("beach"));
+        assert_eq!(key, cipher.encrypt_name("photos/2024/beach.jpg"));
+        assert_eq!(
+            cipher.decrypt_name(&key).as_deref(),
+            Some("photos/2024/beach.jpg")
+        );
+        assert_eq!(cipher.decrypt_name("photos/beach.jpg"), None);
+    }
+}
@@ -0,0 +1,168 @@
+use std::{fs, io, path::PathBuf};
+
+use tracing::warn;
+
+use super::{
+    file_viewer::{Details, FileKind, Files, SyncState},
+    hash_cache::HashCache,
+    FileHash, FilePath,
+};
+use crate::{hash::HashAlgorithm, logging::get_data_dir};
+
+/// Copies of every file as it was the last time both sides agreed, used as the common
+/// ancestor of three-way merges.
+pub struct SyncBase {
+    directory: PathBuf,
+    hashes: HashCache,
+}
+
+impl SyncBase {
+    pub fn new(remote_directory: &str, algorithm: HashAlgorithm) -> Self {
+        SyncBase::open(
+            get_data_dir().join("base").join(remote_directory),
+            HashCache::open(
+                get_data_dir().join("base-hashes").join(remote_directory),
+                algorithm,
+            ),
+        )
+    }
+
+    /// The copies kept in `directory`, with their hashes cached in `hashes`.
+    pub fn open(directory: PathBuf, hashes: HashCache) -> Self {
+        SyncBase { directory, hashes }
+    }
+
+    /// Every path that has a copy.
+    pub fn paths(&self) -> Vec<FilePath> {
+        let mut paths = Vec::new();
+        let mut folders = vec![self.directory.clone()];
+        while let Some(folder) = folders.pop() {
+            let Ok(entries) = fs::read_dir(&folder) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let entry_path = entry.path();
+                if entry_path.is_dir() {
+                    folders.push(entry_path);
+                } else if let Some(path) = entry_path
+                    .strip_prefix(&self.directory)
+                    .ok()
+                    .and_then(|path| path.to_str())
+                {
+                    paths.push(path.to_string());
+                }
+            }
+        }
+        paths
+    }
+
+    pub fn get(&self, path: &str) -> Option<Vec<u8>> {
+        fs::read(self.directory.join(path)).ok()
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.directory.join(path).is_file()
+    }
+
+    /// The hash of the base copy of `path`, only read when its cached hash is stale.
+    fn hash(&mut self, path: &str) -> Option<FileHash> {
+        let base_path = self.directory.join(path);
+        let metadata = fs::metadata(&base_path).ok()?;
+        if let Some(hash) = self.hashes.reuse(path, &metadata) {
+            return Some(hash);
+        }
+        let contents = fs::read(&base_path).ok()?;
+        let metadata = fs::metadata(&base_path).ok()?;
+        Some(self.hashes.hash(path, &metadata, &contents))
+    }
+
+    /// Whether the base copy of `path` hashes to `hash`. Hashes in another algorithm, like
+    /// legacy MD5s, are checked against the copy itself.
+    pub fn matches(&mut self, path: &str, hash: &FileHash) -> bool {
+        match self.hash(path) {
+            Some(base) if base.algorithm() == hash.algorithm() => base == *hash,
+            Some(_) => self.get(path).is_some_and(|base| hash.matches(&base)),
+            None => false,
+        }
+    }
+
+    pub fn record(&mut self, path: &str, hash: FileHash, contents: &[u8]) -> io::Result<()> {
+        let base_path = self.directory.join(path);
+        if let Some(parent) = base_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&base_path, contents)?;
+        self.hashes.record(path, &fs::metadata(&base_path)?, hash);
+        Ok(())
+    }
+
+    pub fn forget(&self, path: &str) {
+        match fs::remove_file(self.directory.join(path)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                warn!("Failed to forget the synced version of `{path}`: {err}")
+            }
+            _ => {}
+        }
+    }
+
+    /// Works out which side each file changed on since it was last in sync. Runs after
+    /// `update`, and saves the hashes of the base copies either one looked at.
+    pub fn classify(&mut self, files: &Files, details: &mut Details) {
+        for (path, kind) in files {
+            let state = match kind {
+                _ if kind.is_in_sync() => SyncState::InSync,
+                FileKind::OnlyInLocal { .. } if self.exists(path) => SyncState::DeletedRemotely,
+                FileKind::OnlyInLocal { .. } => SyncState::LocalOnly,
+                FileKind::OnlyInRemote { .. } if self.exists(path) => SyncState::DeletedLocally,
+                FileKind::OnlyInRemote { .. } => SyncState::RemoteOnly,
+                FileKind::ExistsInBoth { .. } if !self.exists(path) => SyncState::Differs,
+                FileKind::ExistsInBoth {
+                    local_hash,
+                    remote_hash,
+                    ..
+                } => {
+                    if self.matches(path, remote_hash) {
+                        SyncState::ModifiedLocally
+                    } else if self.matches(path, local_hash) {
+                        SyncState::ModifiedRemotely
+                    } else {
+                        SyncState::ModifiedOnBoth
+                    }
+                }
+            };
+            details.entry(path.clone()).or_default().state = state;
+        }
+        if let Err(err) = self.hashes.save() {
+            warn!("Failed to save the hashes of the synced versions: {err}");
+        }
+    }
+
+    /// Records the contents of every file that is in sync, so the next conflict on it has
+    /// a base to merge from.
+    pub fn update(&mut self, files: &Files) {
+        for (path, kind) in files {
+            let FileKind::ExistsInBoth {
+                local_hash,
+                local_contents,
+                remote_hash,
+                ..
+            } = kind
+            else {
+                continue;
+            };
+            if local_hash != remote_hash || self.matches(path, local_hash) {
+                continue;
+            }
+            let contents = match local_contents.get() {
+                Ok(contents) => contents,
+                Err(err) => {
+                    warn!("Failed to record the synced version of `{path}`: {err}");
+                    continue;
+                }
+            };
+            if let Err(err) = self.record(path, *local_hash, contents) {
+                warn!("Failed to record the synced version of `{path}`: {err}");
+            }
+        }
+    }
+}
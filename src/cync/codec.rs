@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+
+use super::config::Config;
+use super::FileMetaData;
+use crate::{
+    compression, crypto,
+    error::Error,
+    hash::{FileHash, HashAlgorithm, S3Checksum},
+    s3::RemoteObject,
+};
+
+/// Object metadata holding the hash of the file as it is locally, as `<algorithm>:<hex>`. It is
+/// what decoded contents are verified against, since the stored bytes differ once compressed
+/// or encrypted.
+pub const CONTENT_HASH_KEY: &str = "cync-hash";
+// Written by earlier versions, as the bare MD5 in hex.
+const LEGACY_MD5_KEY: &str = "cync-md5";
+/// Object metadata holding the length of the object as uploaded, to tell truncated uploads apart.
+pub const OBJECT_SIZE_KEY: &str = "cync-size";
+/// Object metadata holding the length of the file, which differs from the object's once it is
+/// compressed or encrypted.
+pub const FILE_SIZE_KEY: &str = "cync-file-size";
+
+/// What is uploaded for `contents`: compressed, then encrypted, as configured, along with the
+/// metadata needed to reverse it and the checksum S3 verifies the upload against.
+pub fn encode(
+    config: &Config,
+    path: &str,
+    contents: &[u8],
+) -> (Vec<u8>, HashMap<String, String>, S3Checksum) {
+    let hash = config.hash_algorithm().compute(contents);
+    let mut metadata = HashMap::from([
+        (CONTENT_HASH_KEY.to_string(), hash.to_tagged()),
+        (FILE_SIZE_KEY.to_string(), contents.len().to_string()),
+    ]);
+    let object = match config.compression() {
+        Some(compression) => compression.compress(path, contents.to_vec(), &mut metadata),
+        None => contents.to_vec(),
+    };
+    let object = crypto::seal(config.cipher(), object, &mut metadata);
+    metadata.insert(OBJECT_SIZE_KEY.to_string(), object.len().to_string());
+    let checksum = S3Checksum::new(config.hash_algorithm(), &object);
+    (object, metadata, checksum)
+}
+
+/// Reverses `encode`, returning the file contents and their hash with the configured algorithm.
+/// The stored bytes are checked against S3's checksum, and the decoded contents against the
+/// hash recorded on upload, so damage on either side of the transformation is caught.
+pub fn decode(config: &Config, path: &str, object: RemoteObject) -> Result<FileMetaData, Error> {
+    let RemoteObject {
+        contents: object,
+        metadata,
+        checksum,
+    } = object;
+    // Untransformed objects are only held to S3's checksum, which follows edits made to them
+    // outside of Cync, where the recorded size and hash may have been copied along unchanged.
+    let transformed = is_transformed(&metadata);
+    if transformed {
+        check_size(path, &metadata, object.len() as u64)?;
+    }
+    if let Some(expected) = checksum.as_ref().and_then(S3Checksum::to_hash) {
+        verify(path, expected, &object)?;
+    }
+    let object = crypto::open(config.cipher(), path, object, &metadata)?;
+    let contents = compression::decompress(path, object, &metadata)?;
+    if transformed {
+        if let Some(expected) = stored_hash(&metadata) {
+            verify(path, expected, &contents)?;
+        }
+    }
+    Ok((config.hash_algorithm().compute(&contents), contents))
+}
+
+/// The hash and size of the file an object holds, if its metadata tells them in the configured
+/// algorithm. Untransformed objects go by S3's checksum, which follows edits made outside of
+/// Cync, and transformed ones by what was recorded on upload.
+pub fn describe(
+    config: &Config,
+    metadata: &HashMap<String, String>,
+    length: u64,
+    checksum: Option<&S3Checksum>,
+) -> Option<(FileHash, u64)> {
+    let (hash, size) = if is_transformed(metadata) {
+        let size = metadata.get(FILE_SIZE_KEY)?.parse().ok()?;
+        (stored_hash(metadata)?, size)
+    } else {
+        (checksum?.to_hash()?, length)
+    };
+    (hash.algorithm() == config.hash_algorithm()).then_some((hash, size))
+}
+
+/// Whether the stored bytes of an object differ from the file, so ranged reads of it are useless.
+pub fn is_transformed(metadata: &HashMap<String, String>) -> bool {
+    metadata.contains_key(crypto::ENCRYPTION_KEY)
+        || metadata.contains_key(compression::COMPRESSION_KEY)
+}
+
+/// Fails if the object is not as long as it was when uploaded.
+pub fn check_size(
+    path: &str,
+    metadata: &HashMap<String, String>,
+    actual: u64,
+) -> Result<(), Error> {
+    match metadata
+        .get(OBJECT_SIZE_KEY)
+        .and_then(|size| size.parse().ok())
+    {
+        Some(expected) if expected != actual => Err(Error::Truncated {
+            path: path.to_string(),
+            expected,
+            actual,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// The hash of the file recorded on upload, if the object has one.
+pub fn stored_hash(metadata: &HashMap<String, String>) -> Option<FileHash> {
+    match metadata.get(CONTENT_HASH_KEY) {
+        Some(text) => text.parse().ok(),
+        None => metadata
+            .get(LEGACY_MD5_KEY)
+            .and_then(|hex| {
+                data_encoding::HEXLOWER_PERMISSIVE
+                    .decode(hex.as_bytes())
+                    .ok()
+            })
+            .and_then(|bytes| FileHash::from_bytes(HashAlgorithm::Md5, &bytes)),
+    }
+}
+
+fn verify(path: &str, expected: FileHash, contents: &[u8]) -> Result<(), Error> {
+    let actual = expected.algorithm().compute(contents);
+    if actual == expected {
+        return Ok(());
+    }
+    Err(Error::Corrupted {
+        path: path.to_string(),
+        expected: expected.to_tagged(),
+        actual: actual.to_tagged(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::CompressionConfig;
+
+    #[test]
+    fn test_describe_reads_the_file_from_metadata() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut config = Config::for_tests(directory.path().to_path_buf());
+        let contents = "hello ".repeat(100);
+        let hash = config.hash_algorithm().compute(contents.as_bytes());
+
+        let (object, metadata, checksum) = encode(&config, "notes.txt", contents.as_bytes());
+        let length = object.len() as u64;
+        assert_eq!(
+            describe(&config, &metadata, length, Some(&checksum)),
+            Some((hash, 600))
+        );
+
+        config.compression = Some(CompressionConfig::default());
+        let (object, metadata, checksum) = encode(&config, "notes.txt", contents.as_bytes());
+        assert!(object.len() < 600);
+        assert_eq!(
+            describe(&config, &metadata, object.len() as u64, Some(&checksum)),
+            Some((hash, 600))
+        );
+
+        // A hash in another algorithm cannot be compared with the local ones.
+        config.hash_algorithm = HashAlgorithm::Blake3;
+        assert_eq!(
+            describe(&config, &metadata, object.len() as u64, Some(&checksum)),
+            None
+        );
+    }
+}
@@ -0,0 +1,222 @@
+use std::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
+
+use super::file_viewer::{FileDetails, FileKind};
+use super::{Cync, FilePath};
+use crate::util::{format_size, format_time};
+
+/// A column of the file table. `Path` always comes first, whether it is listed or not.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Column {
+    Path,
+    Size,
+    State,
+    LocalModified,
+    RemoteModified,
+    StorageClass,
+    LocalHash,
+    RemoteHash,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TableConfig {
+    pub columns: Vec<Column>,
+    pub sort_by: Option<Column>,
+    pub descending: bool,
+}
+
+impl Default for TableConfig {
+    fn default() -> Self {
+        TableConfig {
+            columns: vec![
+                Column::Path,
+                Column::State,
+                Column::Size,
+                Column::LocalModified,
+                Column::RemoteModified,
+            ],
+            sort_by: None,
+            descending: false,
+        }
+    }
+}
+
+impl TableConfig {
+    /// The configured columns without duplicates, with `Path` moved to the front.
+    pub fn columns(&self) -> Vec<Column> {
+        let mut columns = vec![Column::Path];
+        for column in &self.columns {
+            if !columns.contains(column) {
+                columns.push(*column);
+            }
+        }
+        columns
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sort {
+    pub column: Column,
+    pub descending: bool,
+}
+
+/// A cell value in a form that orders the way users expect, e.g. sizes numerically.
+#[derive(PartialEq, PartialOrd)]
+enum SortValue {
+    Text(String),
+    Number(u64),
+}
+
+impl Column {
+    pub fn title(self) -> &'static str {
+        match self {
+            Column::Path => "Path",
+            Column::Size => "Size",
+            Column::State => "State",
+            Column::LocalModified => "Local Modified",
+            Column::RemoteModified => "Remote Modified",
+            Column::StorageClass => "Storage Class",
+            Column::LocalHash => "Local Hash",
+            Column::RemoteHash => "Remote Hash",
+        }
+    }
+
+    /// The text shown for a file in this column. `Path` is drawn from the tree instead.
+    pub fn cell(self, path: &str, kind: &FileKind, details: Option<&FileDetails>) -> String {
+        let (local, remote) = sides(kind);
+        match self {
+            Column::Path => path.to_string(),
+            Column::Size => match (local, remote) {
+                (Some((_, local)), Some((_, remote))) if local != remote => {
+                    format!("{} / {}", format_size(local), format_size(remote))
+                }
+                (Some((_, size)), _) | (None, Some((_, size))) => format_size(size),
+                (None, None) => String::new(),
+            },
+            Column::State => details
+                .map(|details| details.state.label().to_string())
+                .unwrap_or_default(),
+            Column::LocalModified => details
+                .and_then(|details| details.local_modified)
+                .map(format_time)
+                .unwrap_or_default(),
+            Column::RemoteModified => details
+                .and_then(|details| details.remote_modified)
+                .map(format_time)
+                .unwrap_or_default(),
+            Column::StorageClass => details
+                .and_then(|details| details.storage_class.clone())
+                .unwrap_or_default(),
+            Column::LocalHash => local.map(|(hash, _)| hash).unwrap_or_default(),
+            Column::RemoteHash => remote.map(|(hash, _)| hash).unwrap_or_default(),
+        }
+    }
+
+    fn sort_value(self, path: &str, kind: &FileKind, details: Option<&FileDetails>) -> SortValue {
+        let timestamp = |time: Option<std::time::SystemTime>| {
+            time.and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                .map_or(0, |since| since.as_secs())
+        };
+        match self {
+            Column::Size => {
+                let (local, remote) = sides(kind);
+                SortValue::Number(local.or(remote).map_or(0, |(_, size)| size))
+            }
+            Column::LocalModified => {
+                SortValue::Number(timestamp(details.and_then(|d| d.local_modified)))
+            }
+            Column::RemoteModified => {
+                SortValue::Number(timestamp(details.and_then(|d| d.remote_modified)))
+            }
+            column => SortValue::Text(column.cell(path, kind, details)),
+        }
+    }
+}
+
+type Side = (String, u64);
+
+/// The hash and size of the local and remote copies, where they exist.
+fn sides(kind: &FileKind) -> (Option<Side>, Option<Side>) {
+    match kind {
+        FileKind::OnlyInLocal { hash, contents } => {
+            (Some((hash.to_string(), contents.size())), None)
+        }
+        FileKind::OnlyInRemote { hash, contents } => {
+            (None, Some((hash.to_string(), contents.size())))
+        }
+        FileKind::ExistsInBoth {
+            local_hash,
+            local_contents,
+            remote_hash,
+            remote_contents,
+        } => (
+            Some((local_hash.to_string(), local_contents.size())),
+            Some((remote_hash.to_string(), remote_contents.size())),
+        ),
+    }
+}
+
+impl Cync {
+    /// Moves the sort to the next column, then back to sorting by name.
+    pub fn cycle_sort(&mut self) {
+        let position = self
+            .sort
+            .and_then(|sort| self.columns.iter().position(|c| *c == sort.column));
+        let next = match position {
+            Some(position) => self.columns.get(position + 1),
+            None => self.columns.first(),
+        };
+        self.sort = next.map(|column| Sort {
+            column: *column,
+            descending: self.sort.is_some_and(|sort| sort.descending),
+        });
+        self.rebuild_tree();
+    }
+
+    pub fn reverse_sort(&mut self) {
+        if let Some(sort) = &mut self.sort {
+            sort.descending = !sort.descending;
+            self.rebuild_tree();
+        }
+    }
+
+    pub(super) fn compare_files(
+        &self,
+        sort: Sort,
+        (a_path, a_kind): (&FilePath, &FileKind),
+        (b_path, b_kind): (&FilePath, &FileKind),
+    ) -> Ordering {
+        let details = &self.files.1;
+        let a = sort.column.sort_value(a_path, a_kind, details.get(a_path));
+        let b = sort.column.sort_value(b_path, b_kind, details.get(b_path));
+        let ordering = a
+            .partial_cmp(&b)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| a_path.cmp(b_path));
+        if sort.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_column_comes_first() {
+        let config = TableConfig {
+            columns: vec![Column::Size, Column::Path, Column::Size, Column::State],
+            ..TableConfig::default()
+        };
+        assert_eq!(
+            config.columns(),
+            vec![Column::Path, Column::Size, Column::State]
+        );
+    }
+}
@@ -1,42 +1,156 @@
-use crate::{error::Error, s3::S3Client};
+use crate::{
+    compression::CompressionConfig,
+    crypto::{Cipher, EncryptionConfig},
+    cync::{
+        codec,
+        columns::TableConfig,
+        trash::{Trash, TrashConfig},
+    },
+    error::Error,
+    hash::{FileHash, HashAlgorithm, S3Checksum},
+    retry::{RetryConfig, RetryPolicy},
+    s3::S3Client,
+    sse::{ServerSideEncryption, SseConfig},
+    throttle::{Throttle, ThrottleConfig},
+    tui::{
+        keys::{KeyBindings, KeyConfig},
+        theme::{Theme, ThemeConfig},
+    },
+};
 use std::{fs, path::PathBuf};
 
-#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(serde::Serialize, serde::Deserialize, Default)]
 pub struct ConfigFile {
     pub remote_directory_name: String,
     pub local_directory_name: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub throttle: Option<ThrottleConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub table: Option<TableConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keys: Option<KeyConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mouse: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<HashAlgorithm>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub theme: Option<ThemeConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<EncryptionConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_side_encryption: Option<SseConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<CompressionConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trash: Option<TrashConfig>,
 }
 
 pub struct Config {
     pub remote_directory_name: String,
     pub local_directory_name: PathBuf,
     pub aws_client: S3Client,
+    pub table: TableConfig,
+    pub keys: KeyBindings,
+    pub mouse: bool,
+    pub hash_algorithm: HashAlgorithm,
+    pub theme: Theme,
+    pub cipher: Option<Cipher>,
+    pub compression: Option<CompressionConfig>,
+    pub trash: TrashConfig,
 }
 
 impl Config {
     pub fn load(aws_config: &aws_config::SdkConfig) -> Result<Self, Error> {
-        let config = toml::from_str::<ConfigFile>(
-            &String::from_utf8(Config::get_config_file_path()?)
-                .map_err(|_| Error::ConfigFileMissing)?,
-        )
-        .map_err(|_| Error::ConfigFileCorrupted)?;
+        let path = Config::get_config_file_path()?;
+        let contents = fs::read(&path).map_err(|source| Error::ConfigFileMissing {
+            path: path.clone(),
+            source,
+        })?;
+        let config = String::from_utf8(contents)
+            .map_err(|source| Error::ConfigFileCorrupted {
+                path: path.clone(),
+                source: source.into(),
+            })
+            .and_then(|contents| {
+                toml::from_str::<ConfigFile>(&contents).map_err(|source| {
+                    Error::ConfigFileCorrupted {
+                        path: path.clone(),
+                        source: source.into(),
+                    }
+                })
+            })?;
+
+        let keys = KeyBindings::new(&config.keys.unwrap_or_default()).map_err(|source| {
+            Error::ConfigFileCorrupted {
+                path: path.clone(),
+                source: source.into(),
+            }
+        })?;
+
+        let theme =
+            Theme::new(&config.theme.unwrap_or_default(), Theme::no_color()).map_err(|source| {
+                Error::ConfigFileCorrupted {
+                    path: path.clone(),
+                    source: source.into(),
+                }
+            })?;
+
+        let cipher = config
+            .encryption
+            .as_ref()
+            .map(Cipher::new)
+            .transpose()
+            .map_err(|source| Error::ConfigFileCorrupted {
+                path: path.clone(),
+                source: source.into(),
+            })?;
+
+        let sse = config
+            .server_side_encryption
+            .as_ref()
+            .map(ServerSideEncryption::new)
+            .transpose()
+            .map_err(|source| Error::ConfigFileCorrupted {
+                path: path.clone(),
+                source: source.into(),
+            })?;
+
+        // Retries are handled by our own policy, stacking the SDK's on top would multiply them.
+        let s3_config = aws_sdk_s3::config::Builder::from(aws_config)
+            .retry_config(aws_config::retry::RetryConfig::disabled())
+            .build();
 
         Ok(Config {
             local_directory_name: config.local_directory_name,
             remote_directory_name: config.remote_directory_name,
-            aws_client: S3Client::new(aws_sdk_s3::Client::new(aws_config)),
+            aws_client: S3Client::new(
+                aws_sdk_s3::Client::from_conf(s3_config),
+                Throttle::new(
+                    config
+                        .throttle
+                        .unwrap_or_default()
+                        .or(ThrottleConfig::from_env()),
+                ),
+                RetryPolicy::new(config.retry.unwrap_or_default()),
+                sse,
+            ),
+            table: config.table.unwrap_or_default(),
+            keys,
+            mouse: config.mouse.unwrap_or(true),
+            hash_algorithm: config.hash.unwrap_or_default(),
+            theme,
+            cipher,
+            compression: config.compression,
+            trash: config.trash.unwrap_or_default(),
         })
     }
 
-    fn get_config_file_path() -> Result<Vec<u8>, Error> {
-        let config_file_path = fs::read(
-            xdg::BaseDirectories::with_prefix(".cync")
-                .map_err(|_| Error::ConfigFileCorrupted)?
-                .get_config_file("config.toml"),
-        )
-        .map_err(|_| Error::ConfigFileMissing)?;
-
-        Ok(config_file_path)
+    fn get_config_file_path() -> Result<PathBuf, Error> {
+        Ok(xdg::BaseDirectories::with_prefix(".cync")
+            .map_err(Error::ConfigDirectory)?
+            .get_config_file("config.toml"))
     }
 
     pub fn local_directory(&self) -> &PathBuf {
@@ -50,4 +164,133 @@ impl Config {
     pub fn aws_client(&self) -> &S3Client {
         &self.aws_client
     }
+
+    pub fn table(&self) -> &TableConfig {
+        &self.table
+    }
+
+    pub fn keys(&self) -> &KeyBindings {
+        &self.keys
+    }
+
+    pub fn mouse(&self) -> bool {
+        self.mouse
+    }
+
+    pub fn hash_algorithm(&self) -> HashAlgorithm {
+        self.hash_algorithm
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    pub fn cipher(&self) -> Option<&Cipher> {
+        self.cipher.as_ref()
+    }
+
+    pub fn compression(&self) -> Option<&CompressionConfig> {
+        self.compression.as_ref()
+    }
+
+    pub fn trash(&self) -> &TrashConfig {
+        &self.trash
+    }
+
+    /// The object key `path` is stored under, which differs when names are encrypted.
+    pub fn remote_key(&self, path: &str) -> String {
+        match self.cipher() {
+            Some(cipher) => cipher.encrypt_name(path),
+            None => path.to_string(),
+        }
+    }
+
+    /// The path an object key stands for, or `None` if it is not a name this config encrypted.
+    pub fn local_path(&self, key: &str) -> Option<String> {
+        match self.cipher() {
+            Some(cipher) => cipher.decrypt_name(key),
+            None => Some(key.to_string()),
+        }
+    }
+
+    /// Uploads `contents` to `path` on the remote, compressed and encrypted as configured. S3
+    /// rejects the upload if it arrives damaged, and the checksum it echoes back is compared with
+    /// the one sent.
+    pub async fn upload(&self, path: &str, contents: &[u8]) -> Result<(), Error> {
+        let (object, metadata, checksum) = codec::encode(self, path, contents);
+        let output = self
+            .aws_client()
+            .put_object(
+                self.remote_directory().to_string(),
+                self.remote_key(path),
+                object,
+                metadata,
+                checksum.clone(),
+            )
+            .await?;
+        let stored = S3Checksum::from_response(output.checksum_sha256(), output.checksum_crc32_c());
+        match stored {
+            Some(stored) if stored != checksum => Err(Error::Corrupted {
+                path: path.to_string(),
+                expected: checksum.to_string(),
+                actual: stored.to_string(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes `contents` to `path` in the local directory, creating missing parent folders.
+    /// A file already there is moved to the trash first, unless `current`, its hash as of the
+    /// last scan, shows it holds the same contents.
+    pub fn write_local_file(
+        &self,
+        path: &str,
+        contents: &[u8],
+        current: Option<FileHash>,
+    ) -> Result<(), Error> {
+        let local_path = self.local_directory().join(path);
+        let unchanged = current == Some(self.hash_algorithm().compute(contents));
+        if local_path.exists() && !unchanged {
+            Trash::new(self).put(path)?;
+        }
+        let parent = local_path
+            .parent()
+            .expect("local files live in a directory");
+        fs::create_dir_all(parent)
+            .and_then(|_| fs::write(&local_path, contents))
+            .map_err(|source| Error::LocalSyncFailed {
+                path: local_path.clone(),
+                source,
+            })
+    }
+}
+
+#[cfg(test)]
+impl Config {
+    /// A config for `local_directory` with every other setting at its default, whose S3 client
+    /// is never meant to be called.
+    pub fn for_tests(local_directory: PathBuf) -> Self {
+        let s3_config = aws_sdk_s3::Config::builder()
+            .behavior_version(aws_sdk_s3::config::BehaviorVersion::latest())
+            .region(aws_sdk_s3::config::Region::new("us-east-1"))
+            .build();
+        Config {
+            remote_directory_name: String::from("test-bucket"),
+            local_directory_name: local_directory,
+            aws_client: S3Client::new(
+                aws_sdk_s3::Client::from_conf(s3_config),
+                Throttle::new(ThrottleConfig::default()),
+                RetryPolicy::new(RetryConfig::default()),
+                None,
+            ),
+            table: TableConfig::default(),
+            keys: KeyBindings::default(),
+            mouse: true,
+            hash_algorithm: HashAlgorithm::default(),
+            theme: Theme::new(&ThemeConfig::default(), true).expect("the default theme is valid"),
+            cipher: None,
+            compression: None,
+            trash: TrashConfig::default(),
+        }
+    }
 }
@@ -0,0 +1,115 @@
+use chrono::Local;
+use gethostname::gethostname;
+
+use super::file_viewer::FileKind;
+use super::{Cync, FilePath, Mode, Operation};
+use crate::error::Error;
+
+// Git-style marker left at the start of every hunk diffy could not merge.
+const CONFLICT_MARKER: &str = "<<<<<<<";
+
+/// The outcome of a three-way merge of the local and remote copies of a file.
+pub enum Merge {
+    Clean(String),
+    /// Contains conflict markers around the hunks that changed on both sides.
+    Conflicted(String),
+}
+
+impl Cync {
+    pub async fn start_conflict_resolution(&mut self) {
+        let can_merge = match &self.selected_file {
+            Some(path) => self.merge_inputs(path).await.is_some(),
+            None => false,
+        };
+        self.mode = Mode::ResolveConflict { can_merge };
+    }
+
+    /// The last synced, local and remote contents of `path`, when all three are text.
+    async fn merge_inputs(&self, path: &str) -> Option<(String, String, String)> {
+        let Some(FileKind::ExistsInBoth {
+            local_contents,
+            remote_contents,
+            ..
+        }) = self.view_files().get(path)
+        else {
+            return None;
+        };
+        let base = self.base.get(path)?;
+        Some((
+            String::from_utf8(base).ok()?,
+            String::from_utf8(local_contents.get().ok()?.to_vec()).ok()?,
+            String::from_utf8(remote_contents.get(&self.config).await.ok()?.to_vec()).ok()?,
+        ))
+    }
+
+    /// Merges the local and remote changes to `path` on top of its last synced version.
+    pub async fn merge(&self, path: &str) -> Option<Merge> {
+        let (base, local, remote) = self.merge_inputs(path).await?;
+        Some(match diffy::merge(&base, &local, &remote) {
+            Ok(merged) => Merge::Clean(merged),
+            Err(conflicted) => Merge::Conflicted(conflicted),
+        })
+    }
+
+    /// Writes a merge result to both sides, unless conflict markers were left in it.
+    pub async fn save_merge(&mut self, path: FilePath, merged: String) -> Result<(), Error> {
+        if merged.lines().any(|line| line.starts_with(CONFLICT_MARKER)) {
+            return self.report(Error::UnresolvedConflict { path });
+        }
+        self.perform(Operation::Merge { path, merged }).await
+    }
+
+    /// Keeps the remote copy under the original name and the local one under a conflict name.
+    pub fn keep_both(&self, path: &FilePath) -> Operation {
+        let name = format!(
+            "{path}.conflict-{}-{}",
+            gethostname().to_string_lossy(),
+            Local::now().format("%Y-%m-%d")
+        );
+        // Every known file counts whatever the filter shows, and so do local files created
+        // since the last reload.
+        let taken = |copy: &str| {
+            self.files.0.contains_key(copy) || self.config.local_directory().join(copy).exists()
+        };
+        let mut copy = name.clone();
+        let mut attempt = 1;
+        while taken(&copy) {
+            attempt += 1;
+            copy = format!("{name}-{attempt}");
+        }
+        Operation::KeepBoth {
+            path: path.clone(),
+            copy,
+        }
+    }
+
+    pub(super) async fn keep_both_copies(&self, path: &str, copy: &str) -> Result<(), Error> {
+        let Some(FileKind::ExistsInBoth {
+            local_hash,
+            local_contents,
+            remote_contents,
+            ..
+        }) = self.view_files().get(path)
+        else {
+            return Err(Error::NotAConflict {
+                path: path.to_string(),
+            });
+        };
+
+        let local_contents = local_contents.get()?;
+        // The local changes are stored under the copy's name on both sides before the original
+        // is overwritten, so a failure part-way never loses them.
+        self.config.upload(copy, local_contents).await?;
+        self.config.write_local_file(copy, local_contents, None)?;
+        let remote_contents = remote_contents.get(&self.config).await?;
+        self.config
+            .write_local_file(path, remote_contents, Some(*local_hash))
+    }
+
+    pub(super) async fn save_merged_file(&self, path: &str, merged: &str) -> Result<(), Error> {
+        let current = self.view_files().get(path).and_then(FileKind::local_hash);
+        self.config
+            .write_local_file(path, merged.as_bytes(), current)?;
+        self.config.upload(path, merged.as_bytes()).await
+    }
+}
@@ -0,0 +1,316 @@
+use lazy_static::lazy_static;
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+};
+use similar::{DiffOp, TextDiff};
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{FontStyle, ThemeSet},
+    parsing::SyntaxSet,
+};
+
+use super::file_viewer::FileKind;
+use super::{Cync, Error, Mode};
+use crate::tui::theme::{Element, Theme};
+
+// How many bytes of a binary file to check for NUL bytes before treating it as binary.
+const BINARY_SNIFF_LEN: usize = 8 * 1024;
+// Number of hex dump rows shown for each side of a binary file.
+const HEX_ROWS: usize = 4;
+
+lazy_static! {
+    static ref SYNTAX_SET: SyntaxSet = SyntaxSet::load_defaults_newlines();
+    static ref THEMES: ThemeSet = ThemeSet::load_defaults();
+}
+
+/// A precomputed comparison of the local and remote copies of a file, in both layouts.
+pub struct DiffView {
+    pub path: String,
+    pub unified: Vec<Line<'static>>,
+    pub left: Vec<Line<'static>>,
+    pub right: Vec<Line<'static>>,
+    pub side_by_side: bool,
+    pub scroll: usize,
+}
+
+impl DiffView {
+    /// Remote is treated as the old version and local as the new one.
+    pub fn new(path: &str, local: &[u8], remote: &[u8], theme: &Theme) -> Self {
+        let (unified, left, right) = match (text(local), text(remote)) {
+            (Some(local), Some(remote)) => text_diff(path, local, remote, theme),
+            _ => {
+                let summary = binary_summary(local, remote);
+                (summary.clone(), summary, Vec::new())
+            }
+        };
+
+        DiffView {
+            path: path.to_string(),
+            unified,
+            left,
+            right,
+            side_by_side: false,
+            scroll: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        if self.side_by_side {
+            self.left.len().max(self.right.len())
+        } else {
+            self.unified.len()
+        }
+    }
+
+    pub fn scroll_by(&mut self, delta: isize) {
+        let max = self.len().saturating_sub(1);
+        self.scroll = self.scroll.saturating_add_signed(delta).min(max);
+    }
+
+    pub fn toggle_layout(&mut self) {
+        self.side_by_side = !self.side_by_side;
+        self.scroll = self.scroll.min(self.len().saturating_sub(1));
+    }
+}
+
+impl Cync {
+    /// Opens the diff pane for the selected file, if it exists on both sides.
+    pub async fn open_diff(&mut self) -> Result<(), Error> {
+        let Some(path) = &self.selected_file else {
+            return Ok(());
+        };
+        if let Some(FileKind::ExistsInBoth {
+            local_contents,
+            remote_contents,
+            ..
+        }) = self.view_files().get(path)
+        {
+            self.diff = Some(DiffView::new(
+                path,
+                local_contents.get()?,
+                remote_contents.get(&self.config).await?,
+                self.config.theme(),
+            ));
+            self.mode = Mode::Diff;
+        }
+        Ok(())
+    }
+
+    /// Closes the diff pane and goes back to the action menu of the file.
+    pub fn close_diff(&mut self) {
+        self.diff = None;
+        self.return_to_file_actions();
+    }
+}
+
+/// `contents` as a string, unless it looks like a binary file.
+pub(super) fn text(contents: &[u8]) -> Option<&str> {
+    let sniff = &contents[..contents.len().min(BINARY_SNIFF_LEN)];
+    if sniff.contains(&0) {
+        return None;
+    }
+    std::str::from_utf8(contents).ok()
+}
+
+/// Highlights every line of `contents` using the syntax matching `path`'s extension, or
+/// splits it into plain lines when the theme has no colours.
+fn highlight(path: &str, contents: &str, theme: &Theme) -> Vec<Vec<Span<'static>>> {
+    let Some(syntax_theme) = theme.syntax().and_then(|name| THEMES.themes.get(name)) else {
+        return contents
+            .split_inclusive('\n')
+            .map(|line| vec![Span::raw(line.trim_end_matches(['\n', '\r']).to_string())])
+            .collect();
+    };
+    let extension = path
+        .rsplit_once('.')
+        .map(|(_, ext)| ext)
+        .unwrap_or_default();
+    let syntax = SYNTAX_SET
+        .find_syntax_by_extension(extension)
+        .unwrap_or_else(|| SYNTAX_SET.find_syntax_plain_text());
+    let mut highlighter = HighlightLines::new(syntax, syntax_theme);
+
+    contents
+        .split_inclusive('\n')
+        .map(|line| match highlighter.highlight_line(line, &SYNTAX_SET) {
+            Ok(ranges) => ranges
+                .into_iter()
+                .map(|(style, text)| {
+                    let fg = style.foreground;
+                    let mut span_style = Style::default().fg(Color::Rgb(fg.r, fg.g, fg.b));
+                    if style.font_style.contains(FontStyle::BOLD) {
+                        span_style = span_style.add_modifier(Modifier::BOLD);
+                    }
+                    if style.font_style.contains(FontStyle::ITALIC) {
+                        span_style = span_style.add_modifier(Modifier::ITALIC);
+                    }
+                    Span::styled(text.trim_end_matches(['\n', '\r']).to_string(), span_style)
+                })
+                .collect(),
+            Err(_) => vec![Span::raw(line.trim_end_matches(['\n', '\r']).to_string())],
+        })
+        .collect()
+}
+
+fn diff_line(
+    sign: &str,
+    spans: &[Span<'static>],
+    gutter: Color,
+    background: Option<Color>,
+) -> Line<'static> {
+    let gutter_style = Style::default().fg(gutter);
+    let mut line_spans = vec![Span::styled(format!("{sign} "), gutter_style)];
+    line_spans.extend(spans.iter().cloned());
+    let line = Line::from(line_spans);
+    match background {
+        Some(bg) => line.style(Style::default().bg(bg)),
+        None => line,
+    }
+}
+
+type Lines = Vec<Line<'static>>;
+
+fn text_diff(path: &str, local: &str, remote: &str, theme: &Theme) -> (Lines, Lines, Lines) {
+    let old = highlight(path, remote, theme);
+    let new = highlight(path, local, theme);
+    let diff = TextDiff::from_lines(remote, local);
+
+    let mut unified = Vec::new();
+    let (mut left, mut right) = (Vec::new(), Vec::new());
+    let removed = |i: usize| {
+        let background = theme.background(Element::RemovedBackground);
+        diff_line("-", &old[i], theme.color(Element::Removed), background)
+    };
+    let added = |i: usize| {
+        let background = theme.background(Element::AddedBackground);
+        diff_line("+", &new[i], theme.color(Element::Added), background)
+    };
+    let unchanged = |line: &[Span<'static>]| diff_line(" ", line, Color::Reset, None);
+
+    for op in diff.ops() {
+        match *op {
+            DiffOp::Equal {
+                old_index,
+                new_index,
+                len,
+            } => {
+                for i in 0..len {
+                    unified.push(unchanged(&new[new_index + i]));
+                    left.push(unchanged(&old[old_index + i]));
+                    right.push(unchanged(&new[new_index + i]));
+                }
+            }
+            DiffOp::Delete {
+                old_index, old_len, ..
+            } => {
+                for i in old_index..old_index + old_len {
+                    unified.push(removed(i));
+                    left.push(removed(i));
+                    right.push(Line::default());
+                }
+            }
+            DiffOp::Insert {
+                new_index, new_len, ..
+            } => {
+                for i in new_index..new_index + new_len {
+                    unified.push(added(i));
+                    left.push(Line::default());
+                    right.push(added(i));
+                }
+            }
+            DiffOp::Replace {
+                old_index,
+                old_len,
+                new_index,
+                new_len,
+            } => {
+                unified.extend((old_index..old_index + old_len).map(removed));
+                unified.extend((new_index..new_index + new_len).map(added));
+                for i in 0..old_len.max(new_len) {
+                    left.push(if i < old_len {
+                        removed(old_index + i)
+                    } else {
+                        Line::default()
+                    });
+                    right.push(if i < new_len {
+                        added(new_index + i)
+                    } else {
+                        Line::default()
+                    });
+                }
+            }
+        }
+    }
+
+    (unified, left, right)
+}
+
+fn binary_summary(local: &[u8], remote: &[u8]) -> Lines {
+    let first_difference = local
+        .iter()
+        .zip(remote)
+        .position(|(l, r)| l != r)
+        .unwrap_or(local.len().min(remote.len()));
+    let start = first_difference - first_difference % 16;
+
+    let mut lines = vec![
+        Line::from("Binary files differ").style(Style::default().add_modifier(Modifier::BOLD)),
+        Line::from(format!(
+            "Local: {} bytes, Remote: {} bytes",
+            local.len(),
+            remote.len()
+        )),
+        Line::from(format!("First difference at offset {first_difference:#x}")),
+        Line::default(),
+        Line::from("Local:"),
+    ];
+    lines.extend(hex_dump(local, start, HEX_ROWS));
+    lines.push(Line::default());
+    lines.push(Line::from("Remote:"));
+    lines.extend(hex_dump(remote, start, HEX_ROWS));
+    lines
+}
+
+/// `rows` lines of 16 bytes from `start` onwards, with offsets and a printable column.
+pub(super) fn hex_dump(contents: &[u8], start: usize, rows: usize) -> Lines {
+    contents
+        .get(start..)
+        .unwrap_or_default()
+        .chunks(16)
+        .take(rows)
+        .enumerate()
+        .map(|(row, chunk)| {
+            let hex = chunk
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let ascii = chunk
+                .iter()
+                .map(|&byte| {
+                    if byte.is_ascii_graphic() || byte == b' ' {
+                        byte as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect::<String>();
+            Line::from(format!("{:08x}  {hex:<47}  |{ascii}|", start + row * 16))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tui::theme::ThemeConfig;
+
+    #[test]
+    fn test_side_by_side_rows_stay_aligned() {
+        let theme = Theme::new(&ThemeConfig::default(), false).unwrap();
+        let view = DiffView::new("notes.txt", b"a\nB\nc\nd\n", b"a\nb\nc\n", &theme);
+        assert_eq!(view.left.len(), view.right.len());
+        assert_eq!(view.unified.len(), 5);
+    }
+}
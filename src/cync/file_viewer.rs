@@ -1,188 +1,363 @@
+use aws_sdk_s3::types::Object;
 use std::{
     collections::{BTreeMap, HashMap},
-    fs,
+    fmt, fs,
+    path::PathBuf,
+    sync::{Arc, OnceLock},
+    time::SystemTime,
 };
-use tracing::info;
+use tokio::sync::OnceCell;
+use tracing::{info, warn};
 use util::walk_directory;
 
-use crate::cync::{Cync, FileMetaData, FilePath};
-use crate::error::{self, Error};
+use crate::cync::{
+    codec, hash_cache::HashCache, snapshot::is_snapshot, trash::Trash, Cync, FileHash, FilePath,
+};
+use crate::error::Error;
+use crate::hash::S3Checksum;
 use crate::util;
 
 use super::config::Config;
 
-enum Source {
-    Remote,
-    Local,
+pub type Files = BTreeMap<FilePath, FileKind>;
+pub type Details = HashMap<FilePath, FileDetails>;
+/// A file found in the local directory.
+pub type LocalFile = (FileHash, LocalContents);
+
+/// The contents of a local file. Files whose hash is cached are only stat'd while scanning,
+/// and read once something needs their bytes, such as pushing or comparing them.
+#[derive(Clone)]
+pub struct LocalContents {
+    path: PathBuf,
+    size: u64,
+    bytes: Arc<OnceLock<Vec<u8>>>,
 }
 
-pub type Files = BTreeMap<FilePath, FileKind>;
+impl LocalContents {
+    pub fn unread(path: PathBuf, size: u64) -> Self {
+        LocalContents {
+            path,
+            size,
+            bytes: Arc::default(),
+        }
+    }
+
+    pub fn loaded(path: PathBuf, contents: Vec<u8>) -> Self {
+        LocalContents {
+            path,
+            size: contents.len() as u64,
+            bytes: Arc::new(OnceLock::from(contents)),
+        }
+    }
+
+    /// The size the file had when it was scanned.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The contents, read from disk the first time they are asked for.
+    pub fn get(&self) -> Result<&[u8], Error> {
+        if let Some(bytes) = self.bytes.get() {
+            return Ok(bytes);
+        }
+        let bytes = fs::read(&self.path).map_err(|source| Error::LocalFileCorrupted {
+            path: self.path.clone(),
+            source,
+        })?;
+        Ok(self.bytes.get_or_init(|| bytes))
+    }
+}
+
+impl fmt::Debug for LocalContents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LocalContents")
+            .field("path", &self.path)
+            .field("size", &self.size)
+            .field("read", &self.bytes.get().is_some())
+            .finish()
+    }
+}
+
+impl PartialEq for LocalContents {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path && self.size == other.size
+    }
+}
+
+/// A file found on the remote.
+pub type RemoteFile = (FileHash, RemoteContents);
+
+/// The decoded contents of a remote object. Objects whose hash and size are in their metadata
+/// are only downloaded once something needs their bytes, such as pulling or comparing them.
+#[derive(Clone)]
+pub struct RemoteContents {
+    path: FilePath,
+    key: String,
+    size: u64,
+    bytes: Arc<OnceCell<Vec<u8>>>,
+}
+
+impl RemoteContents {
+    pub fn unread(path: FilePath, key: String, size: u64) -> Self {
+        RemoteContents {
+            path,
+            key,
+            size,
+            bytes: Arc::default(),
+        }
+    }
+
+    pub fn loaded(path: FilePath, key: String, contents: Vec<u8>) -> Self {
+        RemoteContents {
+            path,
+            key,
+            size: contents.len() as u64,
+            bytes: Arc::new(OnceCell::new_with(Some(contents))),
+        }
+    }
+
+    /// The size of the file, not of the object holding it.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The contents, downloaded and decoded the first time they are asked for.
+    pub async fn get(&self, config: &Config) -> Result<&[u8], Error> {
+        let bytes = self
+            .bytes
+            .get_or_try_init(|| async {
+                let download = config
+                    .aws_client()
+                    .download_object(config.remote_directory().to_string(), self.key.clone())
+                    .await?;
+                codec::decode(config, &self.path, download).map(|(_, contents)| contents)
+            })
+            .await?;
+        Ok(bytes)
+    }
+}
+
+impl fmt::Debug for RemoteContents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemoteContents")
+            .field("key", &self.key)
+            .field("size", &self.size)
+            .field("downloaded", &self.bytes.initialized())
+            .finish()
+    }
+}
 
-pub struct FileViewer(pub BTreeMap<FilePath, FileKind>);
+impl PartialEq for RemoteContents {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key && self.size == other.size
+    }
+}
+
+/// What is known about a file besides its contents.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FileDetails {
+    pub local_modified: Option<SystemTime>,
+    pub remote_modified: Option<SystemTime>,
+    pub storage_class: Option<String>,
+    pub state: SyncState,
+}
+
+/// How a file differs between the two sides, taking the last synced version into account.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SyncState {
+    #[default]
+    InSync,
+    LocalOnly,
+    RemoteOnly,
+    DeletedLocally,
+    DeletedRemotely,
+    ModifiedLocally,
+    ModifiedRemotely,
+    ModifiedOnBoth,
+    /// Differs, but there is no synced version to tell which side changed.
+    Differs,
+}
+
+impl SyncState {
+    pub fn label(self) -> &'static str {
+        match self {
+            SyncState::InSync => "in sync",
+            SyncState::LocalOnly => "local only",
+            SyncState::RemoteOnly => "remote only",
+            SyncState::DeletedLocally => "deleted locally",
+            SyncState::DeletedRemotely => "deleted on remote",
+            SyncState::ModifiedLocally => "modified locally",
+            SyncState::ModifiedRemotely => "modified on remote",
+            SyncState::ModifiedOnBoth => "modified on both sides",
+            SyncState::Differs => "differs",
+        }
+    }
+}
+
+pub struct FileViewer(pub Files, pub Details);
 
 impl FileViewer {
     pub fn new() -> Self {
-        FileViewer(BTreeMap::new())
+        FileViewer(BTreeMap::new(), HashMap::new())
     }
 
     pub async fn load_files(mut self, config: &Config) -> Result<Self, Error> {
         let local_files = FileViewer::load_local(config).await?;
-        let remote_files = FileViewer::fetch_remote(config).await?;
+        let remote_files = FileViewer::fetch_remote(config, &mut self.1).await?;
+        for path in local_files.keys() {
+            let modified = fs::metadata(config.local_directory().join(path))
+                .and_then(|metadata| metadata.modified())
+                .ok();
+            self.1.entry(path.clone()).or_default().local_modified = modified;
+        }
         self.0 = FileViewer::create_viewer(local_files, remote_files);
         Ok(self)
     }
 
     fn create_viewer(
-        local_files: HashMap<FilePath, FileMetaData>,
-        remote_files: HashMap<FilePath, FileMetaData>,
+        local_files: HashMap<FilePath, LocalFile>,
+        remote_files: HashMap<FilePath, RemoteFile>,
     ) -> Files {
-        remote_files
+        let mut files = remote_files
             .into_iter()
-            .map(|(path, (hash, content))| (path, (Source::Remote, hash, content)))
-            .chain(
-                local_files
-                    .into_iter()
-                    .map(|(path, (hash, content))| (path, (Source::Local, hash, content))),
-            )
-            .fold(
-                BTreeMap::<FilePath, FileKind>::new(),
-                |mut acc, (path, (incoming_source, incoming_hash, incoming_content))| {
-                    match acc.get_mut(&path) {
-                        Some(existing) => match existing {
-                            FileKind::OnlyInRemote { hash, contents } => match incoming_source {
-                                Source::Remote => {
-                                    *existing = FileKind::create_remote(
-                                        incoming_hash,
-                                        incoming_content.clone(),
-                                    )
-                                }
-                                Source::Local => {
-                                    *existing = FileKind::create_dual_entry(
-                                        *hash,
-                                        contents.to_vec(),
-                                        incoming_hash,
-                                        incoming_content,
-                                    )
-                                }
-                            },
-                            FileKind::OnlyInLocal { hash, contents } => match incoming_source {
-                                Source::Remote => {
-                                    *existing = FileKind::create_dual_entry(
-                                        incoming_hash,
-                                        incoming_content,
-                                        *hash,
-                                        contents.to_vec(),
-                                    )
-                                }
-                                Source::Local => {
-                                    *existing =
-                                        FileKind::create_local(incoming_hash, incoming_content)
-                                }
-                            },
-                            FileKind::ExistsInBoth {
-                                local_hash,
-                                local_contents,
-                                remote_hash,
-                                remote_contents,
-                            } => match incoming_source {
-                                Source::Remote => {
-                                    *existing = FileKind::create_dual_entry(
-                                        incoming_hash,
-                                        incoming_content,
-                                        *local_hash,
-                                        local_contents.to_vec(),
-                                    )
-                                }
-                                Source::Local => {
-                                    *existing = FileKind::create_dual_entry(
-                                        *remote_hash,
-                                        remote_contents.to_vec(),
-                                        incoming_hash,
-                                        incoming_content,
-                                    )
-                                }
-                            },
-                        },
-                        None => match incoming_source {
-                            Source::Remote => {
-                                acc.insert(
-                                    path.to_owned(),
-                                    FileKind::OnlyInRemote {
-                                        hash: incoming_hash,
-                                        contents: incoming_content,
-                                    },
-                                );
-                            }
-                            Source::Local => {
-                                acc.insert(
-                                    path.to_owned(),
-                                    FileKind::OnlyInLocal {
-                                        hash: incoming_hash,
-                                        contents: incoming_content,
-                                    },
-                                );
-                            }
-                        },
-                    };
-                    acc
+            .map(|(path, (hash, contents))| (path, FileKind::OnlyInRemote { hash, contents }))
+            .collect::<Files>();
+        for (path, (local_hash, local_contents)) in local_files {
+            let kind = match files.remove(&path) {
+                Some(FileKind::OnlyInRemote { hash, contents }) => FileKind::ExistsInBoth {
+                    local_hash,
+                    local_contents,
+                    remote_hash: hash,
+                    remote_contents: contents,
+                },
+                _ => FileKind::OnlyInLocal {
+                    hash: local_hash,
+                    contents: local_contents,
                 },
-            )
+            };
+            files.insert(path, kind);
+        }
+        files
+    }
+
+    /// Lists every synced object in the bucket along with the path it stands for, skipping the
+    /// ones whose names cannot be decrypted.
+    pub(super) async fn list_remote(config: &Config) -> Result<Vec<(FilePath, Object)>, Error> {
+        let mut objects = FileViewer::list_bucket(config).await?;
+        objects.retain(|(path, _)| !is_snapshot(path));
+        Ok(objects)
+    }
+
+    /// Lists the snapshot manifests stored next to the synced objects.
+    pub(super) async fn list_snapshots(config: &Config) -> Result<Vec<(FilePath, Object)>, Error> {
+        let mut objects = FileViewer::list_bucket(config).await?;
+        objects.retain(|(path, _)| is_snapshot(path));
+        Ok(objects)
     }
 
-    async fn fetch_remote(config: &Config) -> Result<HashMap<FilePath, FileMetaData>, Error> {
+    async fn list_bucket(config: &Config) -> Result<Vec<(FilePath, Object)>, Error> {
+        let mut objects = Vec::new();
+        let mut continuation_token = None;
+
+        loop {
+            let output = config
+                .aws_client()
+                .list_objects(config.remote_directory().to_string(), continuation_token)
+                .await?;
+
+            for object in output.contents() {
+                let key = object.key().expect("uploaded objects must have a key");
+                let Some(path) = config.local_path(key) else {
+                    warn!("Skipping `{key}`, its name is not encrypted with the configured key");
+                    continue;
+                };
+                objects.push((path, object.clone()));
+            }
+
+            continuation_token = output.next_continuation_token().map(str::to_string);
+            if continuation_token.is_none() {
+                break;
+            }
+        }
+        Ok(objects)
+    }
+
+    /// The hash of every remote file, read from object metadata where it is recorded. Other
+    /// objects are downloaded to hash them.
+    async fn fetch_remote(
+        config: &Config,
+        details: &mut Details,
+    ) -> Result<HashMap<FilePath, RemoteFile>, Error> {
         let mut remote = HashMap::new();
-        let mut paginated_response = config
-            .aws_client()
-            .list_objects(config.remote_directory().to_string())
-            .await;
-
-        while let Some(result) = paginated_response.next().await {
-            if let Ok(output) = result {
-                for object in output.contents() {
-                    if let Ok(remote_object) = config
+
+        for (path, object) in FileViewer::list_remote(config).await? {
+            let key = object.key().expect("uploaded objects must have a key");
+            let head = config
+                .aws_client()
+                .head_object(config.remote_directory().to_string(), key.to_string())
+                .await?;
+            let metadata = head.metadata().cloned().unwrap_or_default();
+            let checksum =
+                S3Checksum::from_response(head.checksum_sha256(), head.checksum_crc32_c());
+            let length = head.content_length().unwrap_or_default().max(0) as u64;
+            let file = match codec::describe(config, &metadata, length, checksum.as_ref()) {
+                Some((hash, size)) => (
+                    hash,
+                    RemoteContents::unread(path.clone(), key.to_string(), size),
+                ),
+                None => {
+                    let download = config
                         .aws_client()
-                        .get_object(
-                            config.remote_directory().to_string(),
-                            object
-                                .key()
-                                .expect("uploaded objects must have a key")
-                                .to_string(),
-                        )
-                        .await
-                    {
-                        let aggregated_bytes = remote_object
-                            .body
-                            .collect()
-                            .await
-                            .expect("Contents are valid utf-8");
-                        remote.insert(
-                            object
-                                .key()
-                                .expect("Uploaded objects must have a key")
-                                .to_string(),
-                            (
-                                md5::compute(&aggregated_bytes.clone().into_bytes()),
-                                aggregated_bytes.clone().to_vec(),
-                            ),
-                        );
-                    };
+                        .download_object(config.remote_directory().to_string(), key.to_string())
+                        .await?;
+                    // A damaged object is left out rather than keeping every other file from
+                    // loading.
+                    match codec::decode(config, &path, download) {
+                        Ok((hash, contents)) => (
+                            hash,
+                            RemoteContents::loaded(path.clone(), key.to_string(), contents),
+                        ),
+                        Err(err) => {
+                            warn!("Skipping `{path}`, run `cync verify` to check it: {err}");
+                            continue;
+                        }
+                    }
                 }
-            } else {
-                return Err(Error::FailedToFetchRemote);
             };
+            let entry = details.entry(path.clone()).or_default();
+            entry.remote_modified = object
+                .last_modified()
+                .and_then(|time| SystemTime::try_from(*time).ok());
+            entry.storage_class = object
+                .storage_class()
+                .map(|class| class.as_str().to_string());
+            remote.insert(path, file);
         }
 
         info!("Fetched {} object from remote host", remote.keys().count());
         Ok(remote)
     }
 
-    async fn load_local(config: &Config) -> Result<HashMap<FilePath, FileMetaData>, Error> {
+    async fn load_local(config: &Config) -> Result<HashMap<FilePath, LocalFile>, Error> {
         if fs::metadata(config.local_directory())
-            .map_err(|_| Error::LoadingLocalFiles(error::LoadingLocalFiles::FileSystem))?
+            .map_err(|source| Error::LoadingLocalFiles {
+                path: config.local_directory().clone(),
+                source,
+            })?
             .is_dir()
         {
             let top_level_path = config.local_directory();
-            let local_files = walk_directory(config.local_directory(), top_level_path)?;
+            let mut hashes = HashCache::load(config.remote_directory(), config.hash_algorithm());
+            let local_files =
+                walk_directory(config.local_directory(), top_level_path, &mut hashes)?;
+            if let Err(err) = hashes.save() {
+                warn!("Failed to save the hash cache: {err}");
+            }
+            match Trash::new(config).expire() {
+                Ok(0) => {}
+                Ok(expired) => info!("Removed {expired} file(s) past retention from the trash"),
+                Err(err) => warn!("Failed to clear old files from the trash: {err}"),
+            }
             info!("Found {} local files", local_files.keys().count());
             Ok(local_files)
         } else {
@@ -199,82 +374,119 @@ impl FileViewer {
 #[derive(Clone, Debug, PartialEq)]
 pub enum FileKind {
     OnlyInRemote {
-        hash: md5::Digest,
-        contents: Vec<u8>,
+        hash: FileHash,
+        contents: RemoteContents,
     },
     OnlyInLocal {
-        hash: md5::Digest,
-        contents: Vec<u8>,
+        hash: FileHash,
+        contents: LocalContents,
     },
     ExistsInBoth {
-        local_hash: md5::Digest,
-        local_contents: Vec<u8>,
-        remote_hash: md5::Digest,
-        remote_contents: Vec<u8>,
+        local_hash: FileHash,
+        local_contents: LocalContents,
+        remote_hash: FileHash,
+        remote_contents: RemoteContents,
     },
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileStatus {
+    InSync,
+    OnlyInLocal,
+    OnlyInRemote,
+    /// Exists on both sides with different contents.
+    Changed,
+}
+
 impl FileKind {
-    fn create_local(hash: md5::Digest, contents: Vec<u8>) -> Self {
-        FileKind::OnlyInLocal { hash, contents }
+    pub fn status(&self) -> FileStatus {
+        match self {
+            FileKind::OnlyInLocal { .. } => FileStatus::OnlyInLocal,
+            FileKind::OnlyInRemote { .. } => FileStatus::OnlyInRemote,
+            FileKind::ExistsInBoth {
+                local_hash,
+                remote_hash,
+                ..
+            } if local_hash == remote_hash => FileStatus::InSync,
+            FileKind::ExistsInBoth { .. } => FileStatus::Changed,
+        }
+    }
+
+    pub fn is_in_sync(&self) -> bool {
+        self.status() == FileStatus::InSync
     }
 
-    fn create_dual_entry(
-        remote_hash: md5::Digest,
-        remote_contents: Vec<u8>,
-        local_hash: md5::Digest,
-        local_contents: Vec<u8>,
-    ) -> Self {
-        FileKind::ExistsInBoth {
-            local_hash,
-            local_contents,
-            remote_hash,
-            remote_contents,
+    /// The hash of the local copy, if there is one.
+    pub fn local_hash(&self) -> Option<FileHash> {
+        match self {
+            FileKind::OnlyInLocal { hash, .. } => Some(*hash),
+            FileKind::ExistsInBoth { local_hash, .. } => Some(*local_hash),
+            FileKind::OnlyInRemote { .. } => None,
         }
     }
 
-    fn create_remote(hash: md5::Digest, contents: Vec<u8>) -> Self {
-        FileKind::OnlyInRemote { hash, contents }
+    pub fn exists_locally(&self) -> bool {
+        !matches!(self, FileKind::OnlyInRemote { .. })
+    }
+
+    pub fn exists_remotely(&self) -> bool {
+        !matches!(self, FileKind::OnlyInLocal { .. })
+    }
+
+    pub fn can_push(&self) -> bool {
+        !self.is_in_sync() && self.exists_locally()
+    }
+
+    pub fn can_pull(&self) -> bool {
+        !self.is_in_sync() && self.exists_remotely()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::hash::HashAlgorithm;
 
     #[test]
     fn test_file_viewer() {
-        let remote_files: HashMap<FilePath, FileMetaData> = vec![
+        let remote_files: HashMap<FilePath, RemoteFile> = vec![
             (
                 String::from("file1"),
                 (
-                    md5::compute(String::from("file1_contents")),
-                    String::from("file1_contents").as_bytes().to_vec(),
+                    HashAlgorithm::default().compute("file1_contents".as_bytes()),
+                    RemoteContents::loaded(
+                        String::from("file1"),
+                        String::from("file1"),
+                        String::from("file1_contents").as_bytes().to_vec(),
+                    ),
                 ),
             ),
             (
                 String::from("file2"),
                 (
-                    md5::compute(String::from("file2_contents")),
-                    String::from("file2_contents").as_bytes().to_vec(),
+                    HashAlgorithm::default().compute("file2_contents".as_bytes()),
+                    RemoteContents::unread(String::from("file2"), String::from("file2"), 14),
                 ),
             ),
         ]
         .into_iter()
         .collect();
-        let local_files: HashMap<FilePath, FileMetaData> = vec![
+        let local_files: HashMap<FilePath, LocalFile> = vec![
             (
                 String::from("file2"),
                 (
-                    md5::compute(String::from("file2_contents")),
-                    String::from("file2_contents").as_bytes().to_vec(),
+                    HashAlgorithm::default().compute("file2_contents".as_bytes()),
+                    LocalContents::loaded(
+                        PathBuf::from("file2"),
+                        String::from("file2_contents").as_bytes().to_vec(),
+                    ),
                 ),
             ),
             (
                 String::from("file3"),
                 (
-                    md5::compute(String::from("file3_contents")),
-                    String::from("file3_contents").as_bytes().to_vec(),
+                    HashAlgorithm::default().compute("file3_contents".as_bytes()),
+                    LocalContents::unread(PathBuf::from("file3"), 14),
                 ),
             ),
         ]
@@ -0,0 +1,128 @@
+use super::file_viewer::{FileKind, FileStatus};
+use super::{Cync, Mode};
+
+/// Quick filters restricting the table to files in a given sync state.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StatusFilter {
+    OutOfSync,
+    OnlyLocal,
+    OnlyRemote,
+    Conflicts,
+}
+
+impl StatusFilter {
+    pub fn matches(self, status: FileStatus) -> bool {
+        match self {
+            StatusFilter::OutOfSync => status != FileStatus::InSync,
+            StatusFilter::OnlyLocal => status == FileStatus::OnlyInLocal,
+            StatusFilter::OnlyRemote => status == FileStatus::OnlyInRemote,
+            StatusFilter::Conflicts => status == FileStatus::Changed,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            StatusFilter::OutOfSync => "out of sync",
+            StatusFilter::OnlyLocal => "only local",
+            StatusFilter::OnlyRemote => "only remote",
+            StatusFilter::Conflicts => "conflicts",
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Filter {
+    pub query: String,
+    pub status: Option<StatusFilter>,
+}
+
+impl Filter {
+    pub fn is_active(&self) -> bool {
+        !self.query.is_empty() || self.status.is_some()
+    }
+
+    pub fn matches(&self, path: &str, kind: &FileKind) -> bool {
+        self.status
+            .is_none_or(|status| status.matches(kind.status()))
+            && fuzzy_match(&self.query, path)
+    }
+
+    /// Switches to `status`, or turns the status filter off if it is already selected.
+    pub fn toggle_status(&mut self, status: StatusFilter) {
+        self.status = if self.status == Some(status) {
+            None
+        } else {
+            Some(status)
+        };
+    }
+
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(status) = self.status {
+            parts.push(status.label().to_string());
+        }
+        if !self.query.is_empty() {
+            parts.push(format!("/{}", self.query));
+        }
+        parts.join(", ")
+    }
+}
+
+impl Cync {
+    pub fn start_search(&mut self) {
+        self.mode = Mode::Search;
+    }
+
+    pub fn push_search_char(&mut self, c: char) {
+        self.filter.query.push(c);
+        self.rebuild_tree();
+    }
+
+    pub fn pop_search_char(&mut self) {
+        self.filter.query.pop();
+        self.rebuild_tree();
+    }
+
+    /// Leaves search mode, keeping the query as a filter.
+    pub fn finish_search(&mut self) {
+        self.mode = Mode::Default;
+    }
+
+    pub fn cancel_search(&mut self) {
+        self.filter.query.clear();
+        self.mode = Mode::Default;
+        self.rebuild_tree();
+    }
+
+    pub fn toggle_status_filter(&mut self, status: StatusFilter) {
+        self.filter.toggle_status(status);
+        self.rebuild_tree();
+    }
+
+    pub fn clear_filter(&mut self) {
+        self.filter = Filter::default();
+        self.rebuild_tree();
+    }
+}
+
+/// Whether every character of `query` appears in `candidate` in order, ignoring case.
+pub fn fuzzy_match(query: &str, candidate: &str) -> bool {
+    let mut candidate = candidate.chars().flat_map(char::to_lowercase);
+    query
+        .chars()
+        .flat_map(char::to_lowercase)
+        .all(|wanted| candidate.any(|c| c == wanted))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_match() {
+        assert!(fuzzy_match("", "notes/todo.md"));
+        assert!(fuzzy_match("ntd", "notes/todo.md"));
+        assert!(fuzzy_match("TODO", "notes/todo.md"));
+        assert!(!fuzzy_match("dton", "notes/todo.md"));
+    }
+}
@@ -0,0 +1,204 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    os::unix::fs::MetadataExt,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use tracing::warn;
+
+use super::{FileHash, FilePath};
+use crate::{hash::HashAlgorithm, logging::get_data_dir};
+
+// Files changed this recently are not cached, since another write within the filesystem's
+// timestamp resolution could leave size and mtime as they were.
+const RACY_WINDOW: Duration = Duration::from_secs(2);
+
+/// What identifies a version of a file without reading it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Stamp {
+    inode: u64,
+    size: u64,
+    modified: u128,
+}
+
+impl Stamp {
+    fn new(metadata: &fs::Metadata) -> Option<Self> {
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Stamp {
+            inode: metadata.ino(),
+            size: metadata.len(),
+            modified: modified.as_nanos(),
+        })
+    }
+
+    fn is_racy(&self) -> bool {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        now.as_nanos().saturating_sub(self.modified) < RACY_WINDOW.as_nanos()
+    }
+}
+
+/// Hashes of local files from earlier scans, so a file is only hashed again once its inode,
+/// size or mtime change. Kept in the data dir as one `inode size mtime hash path` line per
+/// file, tab separated.
+pub struct HashCache {
+    path: PathBuf,
+    algorithm: HashAlgorithm,
+    cached: HashMap<FilePath, (Stamp, FileHash)>,
+    seen: HashMap<FilePath, (Stamp, FileHash)>,
+}
+
+impl HashCache {
+    /// The cache for the local copy of `remote_directory`. A missing or unreadable cache is
+    /// started over, it only costs a full rehash.
+    pub fn load(remote_directory: &str, algorithm: HashAlgorithm) -> Self {
+        HashCache::open(
+            get_data_dir().join("hashes").join(remote_directory),
+            algorithm,
+        )
+    }
+
+    /// The cache kept at `path`, for files other than the local ones.
+    pub fn open(path: PathBuf, algorithm: HashAlgorithm) -> Self {
+        let mut cache = HashCache {
+            path,
+            algorithm,
+            cached: HashMap::new(),
+            seen: HashMap::new(),
+        };
+        match fs::read_to_string(&cache.path) {
+            Ok(contents) => cache.cached = contents.lines().filter_map(parse_line).collect(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => warn!("Failed to read the hash cache, every file is hashed again: {err}"),
+        }
+        cache
+    }
+
+    /// An empty cache in the same place, for hashing every file again and saving over it.
+    pub fn rebuild(remote_directory: &str, algorithm: HashAlgorithm) -> Self {
+        HashCache {
+            path: get_data_dir().join("hashes").join(remote_directory),
+            algorithm,
+            cached: HashMap::new(),
+            seen: HashMap::new(),
+        }
+    }
+
+    /// The cached hash of `path`, if the file is still as it was when it was hashed.
+    pub fn cached(&self, path: &str, metadata: &fs::Metadata) -> Option<FileHash> {
+        let stamp = Stamp::new(metadata)?;
+        self.cached
+            .get(path)
+            .filter(|(cached, hash)| *cached == stamp && hash.algorithm() == self.algorithm)
+            .map(|(_, hash)| *hash)
+    }
+
+    /// Like `cached`, and keeps the hash when the cache is saved.
+    pub fn reuse(&mut self, path: &str, metadata: &fs::Metadata) -> Option<FileHash> {
+        let stamp = Stamp::new(metadata)?;
+        let hash = self.cached(path, metadata)?;
+        self.seen.insert(path.to_string(), (stamp, hash));
+        Some(hash)
+    }
+
+    /// The hash of the file at `path`, which was read as `contents` and then stat'd as
+    /// `metadata`.
+    pub fn hash(&mut self, path: &str, metadata: &fs::Metadata, contents: &[u8]) -> FileHash {
+        let stamp = Stamp::new(metadata);
+        let hash = self
+            .cached(path, metadata)
+            .unwrap_or_else(|| self.algorithm.compute(contents));
+        if let Some(stamp) = stamp.filter(|stamp| !stamp.is_racy()) {
+            self.seen.insert(path.to_string(), (stamp, hash));
+        }
+        hash
+    }
+
+    /// Keeps `hash` for a file that only cync writes, which is trusted within the racy window.
+    pub fn record(&mut self, path: &str, metadata: &fs::Metadata, hash: FileHash) {
+        if let Some(stamp) = Stamp::new(metadata) {
+            self.seen.insert(path.to_string(), (stamp, hash));
+        }
+    }
+
+    /// Writes out the files hashed since loading, which drops the ones that are gone.
+    pub fn save(&self) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut contents = String::new();
+        for (path, (stamp, hash)) in &self.seen {
+            if path.contains('\n') {
+                continue;
+            }
+            contents.push_str(&format!(
+                "{}\t{}\t{}\t{}\t{path}\n",
+                stamp.inode,
+                stamp.size,
+                stamp.modified,
+                hash.to_tagged()
+            ));
+        }
+        // Written aside and renamed over, so an interrupted save cannot leave half a cache.
+        let partial = self.path.with_extension("partial");
+        fs::write(&partial, contents)?;
+        fs::rename(partial, &self.path)
+    }
+}
+
+fn parse_line(line: &str) -> Option<(FilePath, (Stamp, FileHash))> {
+    let mut fields = line.splitn(5, '\t');
+    let stamp = Stamp {
+        inode: fields.next()?.parse().ok()?,
+        size: fields.next()?.parse().ok()?,
+        modified: fields.next()?.parse().ok()?,
+    };
+    let hash = fields.next()?.parse().ok()?;
+    Some((fields.next()?.to_string(), (stamp, hash)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unchanged_files_are_not_rehashed() {
+        let temp = tempfile::tempdir().unwrap();
+        let directory = temp.path();
+        let file = directory.join("notes.txt");
+        fs::write(&file, "first").unwrap();
+        let metadata = fs::metadata(&file).unwrap();
+        let stamp = Stamp::new(&metadata).unwrap();
+
+        let stale = HashAlgorithm::Sha256.compute(b"stale");
+        let mut cache = HashCache {
+            path: directory.join("cache"),
+            algorithm: HashAlgorithm::Sha256,
+            cached: HashMap::from([(String::from("notes.txt"), (stamp, stale))]),
+            seen: HashMap::new(),
+        };
+        // A matching stamp is trusted without looking at the contents.
+        assert_eq!(cache.hash("notes.txt", &metadata, b"first"), stale);
+
+        cache.algorithm = HashAlgorithm::Blake3;
+        assert_eq!(
+            cache.hash("notes.txt", &metadata, b"first"),
+            HashAlgorithm::Blake3.compute(b"first")
+        );
+
+        let line = format!(
+            "{}\t{}\t{}\t{}\tdocs/a\tb.txt",
+            stamp.inode,
+            stamp.size,
+            stamp.modified,
+            stale.to_tagged()
+        );
+        assert_eq!(
+            parse_line(&line),
+            Some((String::from("docs/a\tb.txt"), (stamp, stale)))
+        );
+    }
+}
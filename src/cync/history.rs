@@ -0,0 +1,271 @@
+use std::time::SystemTime;
+
+use ratatui::widgets::TableState;
+
+use super::config::Config;
+use super::file_viewer::FileKind;
+use super::snapshot::is_snapshot;
+use super::tree::TreeNode;
+use super::{codec, Cync, FilePath, Mode, Operation};
+use crate::error::Error;
+
+/// One version of a file on the remote, or the marker left where it was deleted.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Version {
+    pub path: FilePath,
+    pub version_id: String,
+    pub modified: Option<SystemTime>,
+    /// The size of the stored object, which differs from the file once compressed or encrypted.
+    pub size: Option<u64>,
+    pub is_latest: bool,
+    pub delete_marker: bool,
+}
+
+impl Version {
+    /// Restoring removes the latest delete marker, or copies an earlier version on top.
+    pub fn can_restore(&self) -> bool {
+        self.delete_marker == self.is_latest
+    }
+
+    pub fn can_download(&self) -> bool {
+        !self.delete_marker
+    }
+}
+
+/// The versions listed in the history pane.
+pub struct History {
+    pub title: String,
+    /// Whether this lists the deleted files of the bucket rather than the versions of one.
+    pub deleted_files: bool,
+    pub versions: Vec<Version>,
+    pub state: TableState,
+}
+
+impl History {
+    pub fn selected(&self) -> Option<&Version> {
+        self.state.selected().and_then(|i| self.versions.get(i))
+    }
+
+    pub fn move_by(&mut self, delta: isize) {
+        let last = self.versions.len().saturating_sub(1);
+        let i = self
+            .state
+            .selected()
+            .unwrap_or_default()
+            .saturating_add_signed(delta)
+            .min(last);
+        self.state.select(Some(i));
+    }
+}
+
+/// Every version and delete marker of the keys under `prefix`, newest first within a path.
+pub(super) async fn list_versions(
+    config: &Config,
+    prefix: Option<String>,
+) -> Result<Vec<Version>, Error> {
+    let mut versions = Vec::new();
+    let mut markers = None;
+    loop {
+        let output = config
+            .aws_client()
+            .list_object_versions(
+                config.remote_directory().to_string(),
+                prefix.clone(),
+                markers,
+            )
+            .await?;
+
+        let modified = |time: Option<&aws_sdk_s3::primitives::DateTime>| {
+            time.and_then(|time| SystemTime::try_from(*time).ok())
+        };
+        let stored = output.versions().iter().map(|version| {
+            (
+                version.key(),
+                version.version_id(),
+                modified(version.last_modified()),
+                version.size().map(|size| size.max(0) as u64),
+                version.is_latest(),
+                false,
+            )
+        });
+        let deleted = output.delete_markers().iter().map(|marker| {
+            (
+                marker.key(),
+                marker.version_id(),
+                modified(marker.last_modified()),
+                None,
+                marker.is_latest(),
+                true,
+            )
+        });
+        for (key, version_id, modified, size, is_latest, delete_marker) in stored.chain(deleted) {
+            let (Some(path), Some(version_id)) =
+                (key.and_then(|k| config.local_path(k)), version_id)
+            else {
+                continue;
+            };
+            versions.push(Version {
+                path,
+                version_id: version_id.to_string(),
+                modified,
+                size,
+                is_latest: is_latest.unwrap_or_default(),
+                delete_marker,
+            });
+        }
+
+        markers = output
+            .next_key_marker()
+            .zip(output.next_version_id_marker())
+            .map(|(key, version)| (key.to_string(), version.to_string()));
+        if !output.is_truncated().unwrap_or_default() || markers.is_none() {
+            break;
+        }
+    }
+    versions.sort_by(|a, b| a.path.cmp(&b.path).then(b.modified.cmp(&a.modified)));
+    Ok(versions)
+}
+
+impl Cync {
+    /// Opens the history pane with every version of the selected file.
+    pub async fn open_history(&mut self) -> Result<(), Error> {
+        let cursor = self.table_state.selected().and_then(|i| self.tree.get(i));
+        let path = match (&self.selected_file, cursor) {
+            (Some(path), _) | (None, Some(TreeNode::File { path, .. })) => path.clone(),
+            _ => return Ok(()),
+        };
+        let prefix = self.config.remote_key(&path);
+        match list_versions(&self.config, Some(prefix)).await {
+            Ok(mut versions) => {
+                versions.retain(|version| version.path == path);
+                self.show_history(path, false, versions);
+                Ok(())
+            }
+            Err(err) => self.report(err),
+        }
+    }
+
+    /// Opens the history pane with every file whose latest version is a delete marker.
+    pub async fn open_deleted_files(&mut self) -> Result<(), Error> {
+        match list_versions(&self.config, None).await {
+            Ok(mut versions) => {
+                versions.retain(|version| {
+                    version.delete_marker && version.is_latest && !is_snapshot(&version.path)
+                });
+                self.show_history(String::from("Deleted files"), true, versions);
+                Ok(())
+            }
+            Err(err) => self.report(err),
+        }
+    }
+
+    fn show_history(&mut self, title: String, deleted_files: bool, versions: Vec<Version>) {
+        let selected = (!versions.is_empty()).then_some(0);
+        self.history = Some(History {
+            title,
+            deleted_files,
+            versions,
+            state: TableState::default().with_selected(selected),
+        });
+        self.mode = Mode::History;
+    }
+
+    /// Closes the history pane and goes back to where it was opened from.
+    pub fn close_history(&mut self) {
+        self.history = None;
+        self.return_to_file_actions();
+    }
+
+    /// Makes the selected version the current one again.
+    pub async fn restore_selected_version(&mut self) -> Result<(), Error> {
+        let Some(version) = self.history.as_ref().and_then(History::selected).cloned() else {
+            return Ok(());
+        };
+        if !version.can_restore() {
+            return Ok(());
+        }
+        self.history = None;
+        self.perform(Operation::RestoreVersion {
+            path: version.path,
+            version_id: version.version_id,
+            undelete: version.delete_marker,
+        })
+        .await
+    }
+
+    /// Writes the selected version over the local copy of the file.
+    pub async fn download_selected_version(&mut self) -> Result<(), Error> {
+        let Some(version) = self.history.as_ref().and_then(History::selected).cloned() else {
+            return Ok(());
+        };
+        if !version.can_download() {
+            return Ok(());
+        }
+        self.history = None;
+        self.perform(Operation::DownloadVersion {
+            path: version.path,
+            version_id: version.version_id,
+        })
+        .await
+    }
+
+    /// Removes the delete marker `version_id` when undeleting, and otherwise uploads the
+    /// contents of `version_id` as a new version on top.
+    pub(super) async fn restore_version(
+        &self,
+        path: &str,
+        version_id: &str,
+        undelete: bool,
+    ) -> Result<(), Error> {
+        let client = self.config.aws_client();
+        let bucket = self.config.remote_directory().to_string();
+        let key = self.config.remote_key(path);
+        if undelete {
+            return client
+                .delete_version(bucket, key, version_id.to_string())
+                .await;
+        }
+        let object = client
+            .download_version(bucket, key, version_id.to_string())
+            .await?;
+        let (_, contents) = codec::decode(&self.config, path, object)?;
+        self.config.upload(path, &contents).await
+    }
+
+    pub(super) async fn download_version(&self, path: &str, version_id: &str) -> Result<(), Error> {
+        let object = self
+            .config
+            .aws_client()
+            .download_version(
+                self.config.remote_directory().to_string(),
+                self.config.remote_key(path),
+                version_id.to_string(),
+            )
+            .await?;
+        let (_, contents) = codec::decode(&self.config, path, object)?;
+        let current = self.view_files().get(path).and_then(FileKind::local_hash);
+        self.config.write_local_file(path, &contents, current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_earlier_versions_and_latest_markers_restore() {
+        let version = |is_latest, delete_marker| Version {
+            path: String::from("notes.txt"),
+            version_id: String::from("v1"),
+            modified: None,
+            size: None,
+            is_latest,
+            delete_marker,
+        };
+        assert!(!version(true, false).can_restore());
+        assert!(version(false, false).can_restore());
+        assert!(version(true, true).can_restore());
+        assert!(!version(false, true).can_restore());
+        assert!(!version(true, true).can_download());
+    }
+}
@@ -1,25 +1,47 @@
-use aws_sdk_s3::primitives::ByteStream;
 use config::Config;
 use ratatui::widgets::TableState;
 use std::cmp;
-use std::fs;
 use std::sync::Arc;
 use tokio::fs::create_dir;
-use tracing::info;
+use tracing::{error, info};
 use unicode_width::UnicodeWidthStr;
 
+use std::collections::{BTreeSet, HashSet};
+
 use crate::error::Error;
 use crate::trace_dbg;
 
+use self::base::SyncBase;
+use self::columns::{Column, Sort};
+use self::diff::DiffView;
 use self::file_viewer::FileKind;
 use self::file_viewer::FileViewer;
 use self::file_viewer::Files;
+use self::filter::Filter;
+use self::history::History;
+use self::preview::Preview;
+use self::trash::{Trash, TrashEntry, TrashView};
+use self::tree::{build_tree, TreeNode};
 
+mod base;
+pub mod codec;
+pub mod columns;
 pub mod config;
+pub mod conflict;
+pub mod diff;
 pub mod file_viewer;
+pub mod filter;
+pub mod hash_cache;
+pub mod history;
+pub mod preview;
+mod selection;
+pub mod snapshot;
+pub mod trash;
+pub mod tree;
+pub mod verify;
 
 pub type FilePath = String;
-pub type FileHash = md5::Digest;
+pub type FileHash = crate::hash::FileHash;
 pub type FileContents = Vec<u8>;
 pub type FileMetaData = (FileHash, FileContents);
 
@@ -27,94 +49,432 @@ pub type FileMetaData = (FileHash, FileContents);
 pub enum Mode {
     Default,
     PendingAction(FileKind),
+    PendingFolderAction(String),
+    /// Extending a range of marked rows, anchored at the given row.
+    Visual(usize),
+    SelectByStatus,
+    /// Typing a search query, the table is filtered as it changes.
+    Search,
+    PendingBatchAction,
+    ConfirmDelete(Vec<FilePath>),
+    /// Comparing the local and remote contents of the selected file.
+    Diff,
+    /// Choosing how to settle a file that changed on both sides.
+    ResolveConflict {
+        can_merge: bool,
+    },
+    /// Browsing earlier versions of a file, or the files deleted from the remote.
+    History,
+    /// Browsing the local files that were overwritten or deleted.
+    Trash,
     NoFilesFound,
 }
 
+/// A user-triggered operation, kept around so it can be retried after a failure.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operation {
+    Push(Vec<FilePath>),
+    Pull(Vec<FilePath>),
+    Delete(Vec<FilePath>),
+    /// Keeps the remote version at `path` and the local one at `copy`, on both sides.
+    KeepBoth {
+        path: FilePath,
+        copy: FilePath,
+    },
+    Merge {
+        path: FilePath,
+        merged: String,
+    },
+    /// Makes an earlier version current again, by removing the delete marker on top of it
+    /// when undeleting.
+    RestoreVersion {
+        path: FilePath,
+        version_id: String,
+        undelete: bool,
+    },
+    DownloadVersion {
+        path: FilePath,
+        version_id: String,
+    },
+    RestoreFromTrash(TrashEntry),
+    Reload,
+}
+
+/// A recoverable error shown on top of the file table.
+pub struct ErrorPopup {
+    pub error: Error,
+    pub retry: Option<Operation>,
+}
+
 pub struct Cync {
     pub mode: Mode,
     pub config: Arc<Config>,
     pub files: FileViewer,
+    pub tree: Vec<TreeNode>,
+    pub collapsed_folders: HashSet<String>,
+    pub filter: Filter,
     pub table_state: TableState,
-    pub selected_file: Option<usize>,
+    pub selected_file: Option<FilePath>,
+    pub marked: BTreeSet<FilePath>,
+    pub error_popup: Option<ErrorPopup>,
+    pub diff: Option<DiffView>,
+    pub history: Option<History>,
+    pub trash: Option<TrashView>,
+    pub preview_open: bool,
+    pub preview: Option<Preview>,
+    pub columns: Vec<Column>,
+    pub sort: Option<Sort>,
+    pub show_help: bool,
+    /// Rows that fit on screen, updated on every draw.
+    pub page_size: usize,
+    base: SyncBase,
 }
 
 impl Cync {
     pub async fn new(aws_config: &aws_config::SdkConfig) -> Result<Self, Error> {
         let config = Arc::new(Config::load(aws_config)?);
-        let files = FileViewer::new().load_files(&config).await?;
-        Ok(Self {
+        let mut files = FileViewer::new().load_files(&config).await?;
+        let mut base = SyncBase::new(config.remote_directory(), config.hash_algorithm());
+        base.update(&files.0);
+        base.classify(&files.0, &mut files.1);
+        let mut cync = Self {
             mode: if files.0.is_empty() {
                 Mode::NoFilesFound
             } else {
                 Mode::Default
             },
             config: Arc::clone(&config),
+            tree: Vec::new(),
+            collapsed_folders: HashSet::new(),
+            filter: Filter::default(),
             files,
             table_state: TableState::default().with_selected(0),
             selected_file: None,
-        })
+            marked: BTreeSet::new(),
+            error_popup: None,
+            diff: None,
+            history: None,
+            trash: None,
+            preview_open: false,
+            preview: None,
+            columns: config.table().columns(),
+            sort: config.table().sort_by.map(|column| Sort {
+                column,
+                descending: config.table().descending,
+            }),
+            show_help: false,
+            page_size: 1,
+            base,
+        };
+        cync.rebuild_tree();
+        Ok(cync)
     }
 
     pub async fn reload_files(&mut self) -> Result<(), Error> {
         self.files = FileViewer::new().load_files(&self.config).await?;
+        self.base.update(&self.files.0);
+        self.base.classify(&self.files.0, &mut self.files.1);
+        self.preview = None;
+        self.rebuild_tree();
         Ok(())
     }
 
+    /// Recomputes the visible rows, keeping the cursor on the same path when it is still shown.
+    pub fn rebuild_tree(&mut self) {
+        let selected_path = self
+            .table_state
+            .selected()
+            .and_then(|index| self.tree.get(index))
+            .map(|node| node.path().to_string());
+
+        // While searching, matches inside collapsed folders are shown anyway.
+        let no_collapsed = HashSet::new();
+        let collapsed = if self.filter.query.is_empty() {
+            &self.collapsed_folders
+        } else {
+            &no_collapsed
+        };
+        let mut files = self
+            .files
+            .0
+            .iter()
+            .filter(|(path, kind)| self.filter.matches(path, kind))
+            .collect::<Vec<_>>();
+        // Files keep this order within their folder, folders stay sorted by name.
+        if let Some(sort) = self.sort {
+            files.sort_by(|a, b| self.compare_files(sort, *a, *b));
+        }
+        self.tree = build_tree(files, collapsed);
+
+        let files = &self.files.0;
+        self.marked.retain(|path| files.contains_key(path));
+
+        let position = selected_path
+            .and_then(|selected| self.tree.iter().position(|node| node.path() == selected));
+        let last = self.tree.len().saturating_sub(1);
+        match position {
+            Some(position) => self.table_state.select(Some(position)),
+            None if self.table_state.selected().is_some_and(|i| i > last) => {
+                self.table_state.select(Some(last))
+            }
+            None => {}
+        }
+    }
+
+    /// Runs `operation` and reloads the file list afterwards. Recoverable failures are
+    /// shown in `error_popup` along with the step to retry; only fatal ones are returned.
+    pub async fn perform(&mut self, operation: Operation) -> Result<(), Error> {
+        self.selected_file = None;
+        self.mode = Mode::Default;
+        self.clear_marks();
+
+        let result = match self.run_operation(&operation).await {
+            Ok(()) => self
+                .reload_files()
+                .await
+                .map_err(|err| (err, Operation::Reload)),
+            Err(failure) => Err(failure),
+        };
+
+        match result {
+            Err((err, failed)) if !err.is_fatal() => {
+                error!("{:?} failed: {err}", failed);
+                self.error_popup = Some(ErrorPopup {
+                    error: err,
+                    retry: Some(failed),
+                });
+                Ok(())
+            }
+            result => result.map_err(|(err, _)| err),
+        }
+    }
+
+    /// Applies `operation` file by file. On failure, returns the error together with the
+    /// part of the operation that has not been done yet.
+    async fn run_operation(&self, operation: &Operation) -> Result<(), (Error, Operation)> {
+        match operation {
+            Operation::Push(paths) => {
+                for (i, path) in paths.iter().enumerate() {
+                    self.push_file_to_remote(path)
+                        .await
+                        .map_err(|err| (err, Operation::Push(paths[i..].to_vec())))?;
+                }
+            }
+            Operation::Pull(paths) => {
+                for (i, path) in paths.iter().enumerate() {
+                    self.pull_file_from_remote(path)
+                        .await
+                        .map_err(|err| (err, Operation::Pull(paths[i..].to_vec())))?;
+                }
+            }
+            Operation::Delete(paths) => self
+                .delete_files(paths)
+                .await
+                .map_err(|err| (err, operation.clone()))?,
+            Operation::KeepBoth { path, copy } => self
+                .keep_both_copies(path, copy)
+                .await
+                .map_err(|err| (err, operation.clone()))?,
+            Operation::Merge { path, merged } => self
+                .save_merged_file(path, merged)
+                .await
+                .map_err(|err| (err, operation.clone()))?,
+            Operation::RestoreVersion {
+                path,
+                version_id,
+                undelete,
+            } => self
+                .restore_version(path, version_id, *undelete)
+                .await
+                .map_err(|err| (err, operation.clone()))?,
+            Operation::DownloadVersion { path, version_id } => self
+                .download_version(path, version_id)
+                .await
+                .map_err(|err| (err, operation.clone()))?,
+            Operation::RestoreFromTrash(entry) => Trash::new(&self.config)
+                .restore(entry)
+                .map_err(|err| (err, operation.clone()))?,
+            Operation::Reload => {}
+        }
+        Ok(())
+    }
+
+    pub async fn retry_failed_operation(&mut self) -> Result<(), Error> {
+        match self.error_popup.take() {
+            Some(ErrorPopup {
+                retry: Some(operation),
+                ..
+            }) => self.perform(operation).await,
+            _ => Ok(()),
+        }
+    }
+
+    /// Shows a recoverable error that has no operation to retry; fatal ones are returned.
+    pub fn report(&mut self, error: Error) -> Result<(), Error> {
+        if error.is_fatal() {
+            return Err(error);
+        }
+        error!("{error}");
+        self.error_popup = Some(ErrorPopup { error, retry: None });
+        Ok(())
+    }
+
+    pub fn dismiss_error(&mut self) {
+        self.error_popup = None;
+    }
+
     pub fn view_files(&self) -> &Files {
         &self.files.0
     }
 
     pub fn select_file(&mut self, index: usize) {
-        let (_, kind) = self.view_files().iter().nth(index).unwrap();
-        self.mode = Mode::PendingAction(kind.clone());
-        self.selected_file = Some(index);
-    }
-
-    pub fn constraint_len_calculator(&self) -> (u16, u16, u16) {
-        let (key_len, local_len, remote_len) = &self.view_files().iter().fold(
-            (0, 0, 0),
-            |(mut path_len, mut remote_len, mut local_len), (path, kind)| {
-                path_len = cmp::max(path_len, UnicodeWidthStr::width(path.as_str()));
-                match kind {
-                    FileKind::OnlyInRemote { hash, .. } => {
-                        remote_len = cmp::max(
-                            remote_len,
-                            UnicodeWidthStr::width(format!("{:?}", hash).as_str()),
-                        );
-                    }
-                    FileKind::OnlyInLocal { hash, .. } => {
-                        local_len = cmp::max(
-                            local_len,
-                            UnicodeWidthStr::width(format!("{:?}", hash).as_str()),
-                        );
-                    }
-                    FileKind::ExistsInBoth {
-                        local_hash,
-                        remote_hash,
-                        ..
-                    } => {
-                        local_len = cmp::max(
-                            local_len,
-                            UnicodeWidthStr::width(format!("{:?}", local_hash).as_str()),
-                        );
-                        remote_len = cmp::max(
-                            remote_len,
-                            UnicodeWidthStr::width(format!("{:?}", remote_hash).as_str()),
-                        );
-                    }
-                }
-                (path_len, remote_len, local_len)
-            },
-        );
+        match self.tree.get(index) {
+            None => {}
+            Some(TreeNode::Folder { path, .. }) => {
+                self.mode = Mode::PendingFolderAction(path.clone());
+            }
+            Some(TreeNode::File { path, .. }) => {
+                let kind = self
+                    .view_files()
+                    .get(path)
+                    .expect("tree only holds known files");
+                self.mode = Mode::PendingAction(kind.clone());
+                self.selected_file = Some(path.clone());
+            }
+        }
+    }
+
+    /// Goes back to the action menu of the selected file, or to the table if it is gone.
+    pub fn return_to_file_actions(&mut self) {
+        match self
+            .selected_file
+            .as_ref()
+            .and_then(|path| self.view_files().get(path))
+        {
+            Some(kind) => self.mode = Mode::PendingAction(kind.clone()),
+            None => self.mode = Mode::Default,
+        }
+    }
 
-        (*key_len as u16, *local_len as u16, *remote_len as u16)
+    /// Files under `folder` that can be pushed and pulled respectively.
+    pub fn folder_actions(&self, folder: &str) -> (Vec<FilePath>, Vec<FilePath>) {
+        self.split_actions(self.folder_files(folder).iter())
+    }
+
+    /// Marked files that can be pushed and pulled respectively.
+    pub fn batch_actions(&self) -> (Vec<FilePath>, Vec<FilePath>) {
+        self.split_actions(self.marked.iter())
+    }
+
+    fn split_actions<'a>(
+        &self,
+        paths: impl Iterator<Item = &'a FilePath>,
+    ) -> (Vec<FilePath>, Vec<FilePath>) {
+        paths.fold((Vec::new(), Vec::new()), |(mut push, mut pull), path| {
+            let kind = &self.view_files()[path];
+            if kind.can_push() {
+                push.push(path.clone());
+            }
+            if kind.can_pull() {
+                pull.push(path.clone());
+            }
+            (push, pull)
+        })
+    }
+
+    pub fn expand_folder(&mut self) {
+        let Some(index) = self.table_state.selected() else {
+            return;
+        };
+        if let Some(TreeNode::Folder { path, .. }) = self.tree.get(index) {
+            if self.collapsed_folders.remove(path) {
+                self.rebuild_tree();
+            }
+        }
+    }
+
+    /// Collapses the selected folder, or the folder containing the selected file.
+    pub fn collapse_folder(&mut self) {
+        let Some(index) = self.table_state.selected() else {
+            return;
+        };
+        let Some(node) = self.tree.get(index) else {
+            return;
+        };
+        let folder = match node {
+            TreeNode::Folder {
+                path,
+                collapsed: false,
+                ..
+            } => Some(path.clone()),
+            node => node
+                .path()
+                .rsplit_once('/')
+                .map(|(parent, _)| parent.to_string()),
+        };
+
+        if let Some(folder) = folder {
+            let position = self.tree.iter().position(|node| node.path() == folder);
+            self.collapsed_folders.insert(folder);
+            self.rebuild_tree();
+            self.table_state.select(position);
+        }
+    }
+
+    pub fn toggle_throttle(&mut self) {
+        let throttle = self.config.aws_client().throttle();
+        if throttle.is_configured() {
+            let enabled = throttle.toggle();
+            info!(
+                "Bandwidth throttling {}",
+                if enabled { "enabled" } else { "disabled" }
+            );
+        }
+    }
+
+    /// The width of the widest cell of each column, headers included.
+    pub fn constraint_len_calculator(&self) -> Vec<u16> {
+        self.columns
+            .iter()
+            .map(|column| {
+                let cells = self.tree.iter().map(|node| match column {
+                    Column::Path => UnicodeWidthStr::width(node.label().as_str()),
+                    column => self
+                        .view_files()
+                        .get(node.path())
+                        .map(|kind| {
+                            let details = self.files.1.get(node.path());
+                            UnicodeWidthStr::width(column.cell(node.path(), kind, details).as_str())
+                        })
+                        .unwrap_or_default(),
+                });
+                cells.fold(UnicodeWidthStr::width(column.title()), cmp::max) as u16
+            })
+            .collect()
+    }
+
+    /// Moves the cursor by `delta` rows, stopping at the first and last rows.
+    pub fn move_cursor(&mut self, delta: isize) {
+        if self.tree.is_empty() {
+            return;
+        }
+        let i = self
+            .table_state
+            .selected()
+            .unwrap_or_default()
+            .saturating_add_signed(delta)
+            .min(self.tree.len() - 1);
+        self.table_state.select(Some(i));
     }
 
     pub fn prev_file(&mut self) {
+        if self.tree.is_empty() {
+            return;
+        }
         let i = match self.table_state.selected() {
             Some(i) => {
                 if i == 0 {
-                    self.view_files().keys().count() - 1
+                    self.tree.len() - 1
                 } else {
                     i - 1
                 }
@@ -126,9 +486,12 @@ impl Cync {
     }
 
     pub fn next_file(&mut self) {
+        if self.tree.is_empty() {
+            return;
+        }
         let i = match self.table_state.selected() {
             Some(i) => {
-                if i >= self.view_files().keys().count() - 1 {
+                if i >= self.tree.len() - 1 {
                     0
                 } else {
                     i + 1
@@ -144,57 +507,79 @@ impl Cync {
         // TODO: If we choose to keep this what do we do about config file?
         info!("Creating default directory");
 
-        if create_dir(config.local_directory()).await.is_ok() {
-            Ok(())
-        } else {
-            Err(Error::FailedToCreateDefaultDirectory)
-        }
+        create_dir(config.local_directory())
+            .await
+            .map_err(|source| Error::FailedToCreateDefaultDirectory {
+                path: config.local_directory().clone(),
+                source,
+            })
     }
 
-    pub async fn push_file_to_remote(&self, index: usize) -> Result<(), Error> {
-        let (path, kind) = self
-            .view_files()
-            .iter()
-            .nth(index)
-            .expect("to pass a valid index");
+    pub async fn push_file_to_remote(&self, path: &FilePath) -> Result<(), Error> {
+        let kind = self.view_files().get(path).expect("to pass a known path");
 
         let content = match kind {
-            FileKind::OnlyInRemote { .. } => Err(Error::RemoteSyncFailed),
-            FileKind::OnlyInLocal { contents, .. } => Ok(contents),
-            FileKind::ExistsInBoth { local_contents, .. } => Ok(local_contents),
+            FileKind::OnlyInRemote { .. } => Err(Error::NothingToPush { path: path.clone() }),
+            FileKind::OnlyInLocal { contents, .. } => contents.get(),
+            FileKind::ExistsInBoth { local_contents, .. } => local_contents.get(),
         }?;
 
-        self.config
-            .aws_client()
-            .put_object(
-                self.config.clone().remote_directory().to_string(),
-                path.to_string(),
-                ByteStream::from(content.clone()),
-            )
-            .await
-            .map_err(|_| Error::RemoteSyncFailed)?;
-        Ok(())
+        self.config.upload(path, content).await
     }
 
-    pub fn pull_file_from_remote(&self, index: usize) -> Result<(), Error> {
-        let (path, kind) = self
-            .view_files()
-            .iter()
-            .nth(index)
-            .expect("to pass a valid index");
+    pub async fn pull_file_from_remote(&self, path: &FilePath) -> Result<(), Error> {
+        let kind = self.view_files().get(path).expect("to pass a known path");
 
         let content = match trace_dbg!(kind) {
             FileKind::OnlyInRemote { contents, .. } => Ok(contents),
             FileKind::ExistsInBoth {
                 remote_contents, ..
             } => Ok(remote_contents),
-            FileKind::OnlyInLocal { .. } => Err(Error::LocalSyncFailed),
+            FileKind::OnlyInLocal { .. } => Err(Error::NothingToPull { path: path.clone() }),
         }?;
-        fs::write(
-            format!("{}/{}", self.config.local_directory().display(), path),
-            content,
-        )
-        .map_err(|_| Error::LocalSyncFailed)?;
+        let content = content.get(&self.config).await?;
+        self.config
+            .write_local_file(path, content, kind.local_hash())
+    }
+
+    /// Removes `paths` from both sides in one go: a single batch request for the remote
+    /// copies, then the local files are moved to the trash.
+    pub async fn delete_files(&self, paths: &[FilePath]) -> Result<(), Error> {
+        let exists = |path: &&FilePath, check: fn(&FileKind) -> bool| {
+            self.view_files().get(*path).is_some_and(check)
+        };
+
+        let remote = paths
+            .iter()
+            .filter(|path| exists(path, FileKind::exists_remotely))
+            .map(|path| self.config.remote_key(path))
+            .collect::<Vec<_>>();
+        if !remote.is_empty() {
+            let failures = self
+                .config
+                .aws_client()
+                .delete_objects(self.config.remote_directory().to_string(), remote)
+                .await?;
+            if let Some(failure) = failures.into_iter().next() {
+                return Err(Error::RemoteDeleteFailed {
+                    path: self.config.local_path(&failure.key).unwrap_or(failure.key),
+                    code: failure.code,
+                    message: failure.message,
+                });
+            }
+        }
+
+        let trash = Trash::new(&self.config);
+        for path in paths
+            .iter()
+            .filter(|path| exists(path, FileKind::exists_locally))
+        {
+            trash.put(path)?;
+        }
+        for path in paths {
+            self.base.forget(path);
+        }
+        info!("Deleted {} file(s)", paths.len());
         Ok(())
     }
 }
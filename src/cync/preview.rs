@@ -0,0 +1,233 @@
+use std::{
+    fs::{self, File},
+    io::Read,
+    time::SystemTime,
+};
+
+use ratatui::{
+    style::{Modifier, Style},
+    text::Line,
+};
+
+use super::codec;
+use super::diff::{hex_dump, text};
+use super::file_viewer::{FileKind, LocalContents};
+use super::tree::TreeNode;
+use super::{Cync, FilePath};
+use crate::error::Error;
+use crate::tui::theme::{Element, Theme};
+use crate::util::{format_size, format_time};
+
+// Number of bytes fetched from the start of a remote file for its preview.
+const PREVIEW_LEN: u64 = 16 * 1024;
+// Number of hex dump rows shown for a binary file.
+const PREVIEW_HEX_ROWS: usize = 32;
+
+/// Metadata and the first bytes of one copy of a file.
+struct SidePreview {
+    size: u64,
+    content_type: Option<String>,
+    last_modified: Option<SystemTime>,
+    storage_class: Option<String>,
+    head: Vec<u8>,
+}
+
+/// The rendered preview of the file under the cursor.
+pub struct Preview {
+    pub path: FilePath,
+    pub lines: Vec<Line<'static>>,
+}
+
+impl Cync {
+    pub fn toggle_preview(&mut self) {
+        self.preview_open = !self.preview_open;
+        self.preview = None;
+    }
+
+    /// Loads the preview of the selected file if the pane is open and it is not loaded yet.
+    /// Remote copies are only read up to `PREVIEW_LEN` bytes.
+    pub async fn refresh_preview(&mut self) {
+        if !self.preview_open {
+            return;
+        }
+        let selected = match self.table_state.selected().and_then(|i| self.tree.get(i)) {
+            Some(TreeNode::File { path, .. }) => path.clone(),
+            _ => {
+                self.preview = None;
+                return;
+            }
+        };
+        if self
+            .preview
+            .as_ref()
+            .is_some_and(|preview| preview.path == selected)
+        {
+            return;
+        }
+
+        let Some(kind) = self.view_files().get(&selected) else {
+            return;
+        };
+        let local = match kind {
+            FileKind::OnlyInLocal { contents, .. }
+            | FileKind::ExistsInBoth {
+                local_contents: contents,
+                ..
+            } => Some(self.local_preview(&selected, contents)),
+            FileKind::OnlyInRemote { .. } => None,
+        };
+        let remote = if kind.exists_remotely() {
+            Some(self.remote_preview(&selected).await)
+        } else {
+            None
+        };
+
+        self.preview = Some(Preview {
+            lines: preview_lines(local.as_ref(), remote.as_ref(), self.config.theme()),
+            path: selected,
+        });
+    }
+
+    /// Only the first `PREVIEW_LEN` bytes are read, the contents may not be loaded yet.
+    fn local_preview(&self, path: &str, contents: &LocalContents) -> Result<SidePreview, Error> {
+        let local_path = self.config.local_directory().join(path);
+        let mut head = Vec::new();
+        File::open(&local_path)
+            .and_then(|file| file.take(PREVIEW_LEN).read_to_end(&mut head))
+            .map_err(|source| Error::LocalFileCorrupted {
+                path: local_path.clone(),
+                source,
+            })?;
+        let metadata = fs::metadata(&local_path).ok();
+        Ok(SidePreview {
+            size: contents.size(),
+            content_type: mime_guess::from_path(path)
+                .first()
+                .map(|mime| mime.to_string()),
+            last_modified: metadata.and_then(|metadata| metadata.modified().ok()),
+            storage_class: None,
+            head,
+        })
+    }
+
+    async fn remote_preview(&self, path: &str) -> Result<SidePreview, Error> {
+        let client = self.config.aws_client();
+        let bucket = self.config.remote_directory().to_string();
+        let key = self.config.remote_key(path);
+        let head = client.head_object(bucket.clone(), key.clone()).await?;
+        let transformed = head.metadata().is_some_and(codec::is_transformed);
+        let mut size = head.content_length().unwrap_or_default().max(0) as u64;
+        let contents = if transformed {
+            // The start of a compressed or encrypted object tells nothing, show the decoded copy.
+            let contents = match self.view_files().get(path) {
+                Some(FileKind::OnlyInRemote { contents, .. })
+                | Some(FileKind::ExistsInBoth {
+                    remote_contents: contents,
+                    ..
+                }) => contents.get(&self.config).await?,
+                _ => &[],
+            };
+            size = contents.len() as u64;
+            contents[..contents.len().min(PREVIEW_LEN as usize)].to_vec()
+        } else if size == 0 {
+            Vec::new()
+        } else {
+            client
+                .download_range(bucket, key, 0..size.min(PREVIEW_LEN))
+                .await?
+        };
+
+        Ok(SidePreview {
+            size,
+            content_type: head.content_type().map(str::to_string),
+            last_modified: head
+                .last_modified()
+                .and_then(|time| SystemTime::try_from(*time).ok()),
+            // S3 leaves the storage class out for STANDARD objects.
+            storage_class: Some(
+                head.storage_class()
+                    .map_or("STANDARD", |class| class.as_str())
+                    .to_string(),
+            ),
+            head: contents,
+        })
+    }
+}
+
+fn preview_lines(
+    local: Option<&Result<SidePreview, Error>>,
+    remote: Option<&Result<SidePreview, Error>>,
+    theme: &Theme,
+) -> Vec<Line<'static>> {
+    let heading = |title: &str| {
+        Line::from(title.to_string()).style(Style::default().add_modifier(Modifier::BOLD))
+    };
+    let mut lines = Vec::new();
+
+    for (title, side) in [("Local", local), ("Remote", remote)] {
+        match side {
+            Some(Ok(side)) => {
+                lines.push(heading(title));
+                lines.extend(metadata_lines(side));
+                lines.push(Line::default());
+            }
+            Some(Err(err)) => {
+                lines.push(heading(title));
+                lines.push(
+                    Line::from(format!("Could not load: {err}"))
+                        .style(Style::default().fg(theme.color(Element::Error))),
+                );
+                lines.push(Line::default());
+            }
+            None => {}
+        }
+    }
+
+    let shown = [local, remote]
+        .into_iter()
+        .flatten()
+        .find_map(|side| side.as_ref().ok());
+    if let Some(shown) = shown {
+        match preview_text(&shown.head) {
+            Some(contents) => {
+                lines.extend(contents.lines().map(|line| Line::from(line.to_string())))
+            }
+            None => lines.extend(hex_dump(&shown.head, 0, PREVIEW_HEX_ROWS)),
+        }
+        if (shown.head.len() as u64) < shown.size {
+            lines.push(
+                Line::from(format!(
+                    "… {} more",
+                    format_size(shown.size - shown.head.len() as u64)
+                ))
+                .style(Style::default().add_modifier(Modifier::DIM)),
+            );
+        }
+    }
+    lines
+}
+
+/// Like `text`, but allows the range to end in the middle of a multi-byte character.
+fn preview_text(head: &[u8]) -> Option<&str> {
+    match std::str::from_utf8(head) {
+        Err(err) if err.error_len().is_none() => text(&head[..err.valid_up_to()]),
+        _ => text(head),
+    }
+}
+
+fn metadata_lines(side: &SidePreview) -> Vec<Line<'static>> {
+    let mut lines = vec![Line::from(format!("Size: {}", format_size(side.size)))];
+    if let Some(content_type) = &side.content_type {
+        lines.push(Line::from(format!("Type: {content_type}")));
+    }
+    if let Some(last_modified) = side.last_modified {
+        lines.push(Line::from(format!(
+            "Modified: {}",
+            format_time(last_modified)
+        )));
+    }
+    if let Some(storage_class) = &side.storage_class {
+        lines.push(Line::from(format!("Storage class: {storage_class}")));
+    }
+    lines
+}
@@ -0,0 +1,90 @@
+use std::collections::BTreeSet;
+
+use super::file_viewer::FileStatus;
+use super::tree::{files_in_folder, TreeNode};
+use super::{Cync, FilePath, Mode};
+
+impl Cync {
+    /// Files covered by the tree row at `index`: the file itself, or everything in a folder.
+    pub fn files_at(&self, index: usize) -> Vec<FilePath> {
+        match self.tree.get(index) {
+            Some(TreeNode::Folder { path, .. }) => self.folder_files(path),
+            Some(TreeNode::File { path, .. }) => vec![path.clone()],
+            None => Vec::new(),
+        }
+    }
+
+    /// Files under `folder` at any depth that pass the current filter.
+    pub fn folder_files(&self, folder: &str) -> Vec<FilePath> {
+        files_in_folder(self.view_files(), folder)
+            .filter(|(path, kind)| self.filter.matches(path, kind))
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+
+    /// Marks the files under the cursor, or unmarks them if they are all marked already.
+    pub fn toggle_mark(&mut self) {
+        let Some(index) = self.table_state.selected() else {
+            return;
+        };
+        let files = self.files_at(index);
+        if files.iter().all(|path| self.marked.contains(path)) {
+            for path in &files {
+                self.marked.remove(path);
+            }
+        } else {
+            self.marked.extend(files);
+        }
+    }
+
+    pub fn start_visual(&mut self) {
+        if let Some(index) = self.table_state.selected() {
+            self.mode = Mode::Visual(index);
+        }
+    }
+
+    /// Adds the rows between the visual anchor and the cursor to the marked files.
+    pub fn finish_visual(&mut self) {
+        self.marked.extend(self.visual_range());
+        self.mode = Mode::Default;
+    }
+
+    pub fn cancel_visual(&mut self) {
+        self.mode = Mode::Default;
+    }
+
+    fn visual_range(&self) -> Vec<FilePath> {
+        match (&self.mode, self.table_state.selected()) {
+            (Mode::Visual(anchor), Some(cursor)) => {
+                let (start, end) = (*anchor.min(&cursor), *anchor.max(&cursor));
+                (start..=end.min(self.tree.len().saturating_sub(1)))
+                    .flat_map(|index| self.files_at(index))
+                    .collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Marked files, including the pending visual range.
+    pub fn marked_files(&self) -> BTreeSet<FilePath> {
+        let mut marked = self.marked.clone();
+        marked.extend(self.visual_range());
+        marked
+    }
+
+    /// Marks every file whose status passes `filter`, on top of the existing marks.
+    pub fn mark_by_status(&mut self, filter: impl Fn(FileStatus) -> bool) {
+        let matching = self
+            .view_files()
+            .iter()
+            .filter(|(_, kind)| filter(kind.status()))
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        self.marked.extend(matching);
+        self.mode = Mode::Default;
+    }
+
+    pub fn clear_marks(&mut self) {
+        self.marked.clear();
+    }
+}
@@ -0,0 +1,553 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use super::config::Config;
+use super::file_viewer::{FileKind, FileViewer, Files};
+use super::history::list_versions;
+use super::trash::{Trash, TrashEntry};
+use super::{codec, FileContents, FileHash, FilePath};
+use crate::error::Error;
+
+/// The folder in the bucket holding snapshot manifests, kept out of the synced files.
+pub const SNAPSHOT_DIRECTORY: &str = ".cync-snapshots";
+
+// Down to the millisecond, so snapshots taken in the same second do not share a manifest.
+const ID_FORMAT: &str = "%Y-%m-%dT%H-%M-%S%.3fZ";
+
+pub fn is_snapshot(path: &str) -> bool {
+    path.strip_prefix(SNAPSHOT_DIRECTORY)
+        .is_some_and(|rest| rest.starts_with('/'))
+}
+
+fn manifest_path(id: &str) -> FilePath {
+    format!("{SNAPSHOT_DIRECTORY}/{id}.toml")
+}
+
+/// Every file of the sync pair at one moment, stored in the bucket like any other file.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub created: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default)]
+    pub files: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub path: FilePath,
+    /// The hash of the contents, as `algorithm:hex`.
+    pub hash: String,
+    pub size: u64,
+    /// The remote version holding these contents, on versioned buckets.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_id: Option<String>,
+}
+
+/// A snapshot as listed by `cync snapshot list`.
+pub struct Snapshot {
+    pub id: String,
+    pub manifest: Manifest,
+}
+
+impl Snapshot {
+    /// Files whose contents no remote version holds, which can only be restored for as long
+    /// as a matching copy is still around locally, on the remote or in the trash.
+    pub fn unversioned(&self) -> usize {
+        self.manifest
+            .files
+            .iter()
+            .filter(|file| file.version_id.is_none())
+            .count()
+    }
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}  {} files", self.id, self.manifest.files.len())?;
+        match self.unversioned() {
+            0 => {}
+            unversioned => write!(f, " ({unversioned} not versioned)")?,
+        }
+        if let Some(label) = &self.manifest.label {
+            write!(f, "  {label}")?;
+        }
+        Ok(())
+    }
+}
+
+/// What restoring a snapshot changed.
+#[derive(Debug, Default, PartialEq)]
+pub struct Restoration {
+    pub written: usize,
+    pub unchanged: usize,
+    /// Files that were not in the snapshot and went to the trash.
+    pub removed: usize,
+}
+
+impl fmt::Display for Restoration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Wrote {} files, left {} unchanged and moved {} to the trash",
+            self.written, self.unchanged, self.removed
+        )
+    }
+}
+
+/// Records every local file, or the remote one for files only on the remote, along with the
+/// remote version that holds the same contents.
+pub async fn create(config: &Config, label: Option<String>) -> Result<Snapshot, Error> {
+    let files = FileViewer::new().load_files(config).await?.0;
+    let versions = list_versions(config, None)
+        .await?
+        .into_iter()
+        // Unversioned buckets report "null", which the next upload replaces.
+        .filter(|version| {
+            version.is_latest && !version.delete_marker && version.version_id != "null"
+        })
+        .map(|version| (version.path, version.version_id))
+        .collect::<HashMap<_, _>>();
+
+    let entries = files
+        .iter()
+        .map(|(path, kind)| {
+            let (hash, size, versioned) = match kind {
+                FileKind::OnlyInRemote { hash, contents } => (hash, contents.size(), true),
+                FileKind::OnlyInLocal { hash, contents } => (hash, contents.size(), false),
+                FileKind::ExistsInBoth {
+                    local_hash,
+                    local_contents,
+                    remote_hash,
+                    ..
+                } => (local_hash, local_contents.size(), local_hash == remote_hash),
+            };
+            ManifestEntry {
+                path: path.clone(),
+                hash: hash.to_tagged(),
+                size,
+                version_id: versions.get(path).filter(|_| versioned).cloned(),
+            }
+        })
+        .collect();
+
+    let now = Utc::now();
+    let id = now.format(ID_FORMAT).to_string();
+    let manifest = Manifest {
+        created: now.to_rfc3339(),
+        label,
+        files: entries,
+    };
+    let contents = toml::to_string(&manifest).map_err(|source| Error::SnapshotCorrupted {
+        id: id.clone(),
+        source: source.into(),
+    })?;
+    config
+        .upload(&manifest_path(&id), contents.as_bytes())
+        .await?;
+    info!("Created snapshot `{id}` of {} files", manifest.files.len());
+    Ok(Snapshot { id, manifest })
+}
+
+/// Every snapshot in the bucket, oldest first.
+pub async fn list(config: &Config) -> Result<Vec<Snapshot>, Error> {
+    let mut snapshots = Vec::new();
+    for (path, _) in FileViewer::list_snapshots(config).await? {
+        let Some(id) = path
+            .strip_prefix(SNAPSHOT_DIRECTORY)
+            .and_then(|rest| rest.strip_prefix('/'))
+            .and_then(|name| name.strip_suffix(".toml"))
+        else {
+            continue;
+        };
+        match load(config, id).await {
+            Ok(snapshot) => snapshots.push(snapshot),
+            Err(err @ Error::Remote(_)) => return Err(err),
+            Err(err) => warn!("Skipping snapshot `{id}`: {err}"),
+        }
+    }
+    snapshots.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(snapshots)
+}
+
+async fn load(config: &Config, id: &str) -> Result<Snapshot, Error> {
+    let path = manifest_path(id);
+    let download = config
+        .aws_client()
+        .download_object(
+            config.remote_directory().to_string(),
+            config.remote_key(&path),
+        )
+        .await
+        .map_err(|err| match err {
+            Error::Remote(remote) if remote.code() == Some("NoSuchKey") => {
+                Error::SnapshotNotFound { id: id.to_string() }
+            }
+            err => err,
+        })?;
+    let (_, contents) = codec::decode(config, &path, download)?;
+    let manifest = String::from_utf8(contents)
+        .map_err(Into::into)
+        .and_then(|contents| toml::from_str(&contents).map_err(Into::into))
+        .map_err(|source| Error::SnapshotCorrupted {
+            id: id.to_string(),
+            source,
+        })?;
+    Ok(Snapshot {
+        id: id.to_string(),
+        manifest,
+    })
+}
+
+/// Brings the local directory, or the empty directory `target`, back to the state recorded in
+/// snapshot `id`. Nothing is written unless the contents of every file can be found.
+pub async fn restore(
+    config: &Config,
+    id: &str,
+    target: Option<PathBuf>,
+) -> Result<Restoration, Error> {
+    let snapshot = load(config, id).await?;
+    let target = target.filter(|target| target != config.local_directory());
+    if let Some(target) = &target {
+        check_target(target)?;
+    }
+    let files = FileViewer::new().load_files(config).await?.0;
+    let restoration = restore_files(config, &snapshot, &files, target.as_deref()).await?;
+    info!("Restored snapshot `{id}`: {restoration}");
+    Ok(restoration)
+}
+
+/// Fails unless `target` is empty or does not exist yet.
+fn check_target(target: &Path) -> Result<(), Error> {
+    let empty = match fs::read_dir(target) {
+        Ok(mut entries) => entries.next().is_none(),
+        Err(_) => !target.exists(),
+    };
+    if !empty {
+        return Err(Error::SnapshotTargetNotEmpty {
+            path: target.to_path_buf(),
+        });
+    }
+    Ok(())
+}
+
+/// Restores `snapshot` over `files`, as loaded from both sides, or into `target`.
+async fn restore_files(
+    config: &Config,
+    snapshot: &Snapshot,
+    files: &Files,
+    target: Option<&Path>,
+) -> Result<Restoration, Error> {
+    let id = &snapshot.id;
+    let mut trashed = None;
+    let mut restored = BTreeMap::new();
+    let mut missing = Vec::new();
+    let mut unchanged = 0;
+    for entry in &snapshot.manifest.files {
+        let hash = entry
+            .hash
+            .parse::<FileHash>()
+            .map_err(|source| Error::SnapshotCorrupted {
+                id: id.to_string(),
+                source: source.into(),
+            })?;
+        let (local, remote) = match files.get(&entry.path) {
+            Some(FileKind::OnlyInLocal { hash, contents }) => (Some((hash, contents)), None),
+            Some(FileKind::OnlyInRemote { hash, contents }) => (None, Some((hash, contents))),
+            Some(FileKind::ExistsInBoth {
+                local_hash,
+                local_contents,
+                remote_hash,
+                remote_contents,
+            }) => (
+                Some((local_hash, local_contents)),
+                Some((remote_hash, remote_contents)),
+            ),
+            None => (None, None),
+        };
+        let local = local.filter(|(local, _)| **local == hash);
+        if target.is_none() && local.is_some() {
+            unchanged += 1;
+            continue;
+        }
+        let contents = match (local, remote.filter(|(remote, _)| **remote == hash)) {
+            (Some((_, contents)), _) => Some(contents.get()?.to_vec()),
+            (None, Some((_, contents))) => Some(contents.get(config).await?.to_vec()),
+            (None, None) => find_elsewhere(config, entry, hash, &mut trashed).await?,
+        };
+        match contents {
+            Some(contents) => {
+                restored.insert(entry.path.clone(), contents);
+            }
+            None => missing.push(entry.path.clone()),
+        }
+    }
+    if !missing.is_empty() {
+        return Err(Error::SnapshotIncomplete {
+            id: id.to_string(),
+            missing,
+        });
+    }
+
+    let mut restoration = Restoration {
+        written: restored.len(),
+        unchanged,
+        removed: 0,
+    };
+    match target {
+        Some(target) => {
+            for (path, contents) in &restored {
+                write_file(target, path, contents)?;
+            }
+        }
+        None => {
+            for (path, contents) in &restored {
+                let current = files.get(path).and_then(FileKind::local_hash);
+                config.write_local_file(path, contents, current)?;
+            }
+            let recorded = &snapshot.manifest.files;
+            let trash = Trash::new(config);
+            for (path, kind) in files {
+                if kind.exists_locally() && !recorded.iter().any(|entry| &entry.path == path) {
+                    trash.put(path)?;
+                    restoration.removed += 1;
+                }
+            }
+        }
+    }
+    Ok(restoration)
+}
+
+/// Looks for contents matching `hash` in the version recorded for them, then in the trash.
+async fn find_elsewhere(
+    config: &Config,
+    entry: &ManifestEntry,
+    hash: FileHash,
+    trashed: &mut Option<Vec<TrashEntry>>,
+) -> Result<Option<FileContents>, Error> {
+    if let Some(version_id) = &entry.version_id {
+        let download = config
+            .aws_client()
+            .download_version(
+                config.remote_directory().to_string(),
+                config.remote_key(&entry.path),
+                version_id.clone(),
+            )
+            .await;
+        // A version removed since is not an error, the contents may still be in the trash.
+        match download {
+            Ok(download) => {
+                let (_, contents) = codec::decode(config, &entry.path, download)?;
+                if hash.matches(&contents) {
+                    return Ok(Some(contents));
+                }
+            }
+            Err(Error::Remote(remote))
+                if matches!(remote.code(), Some("NoSuchVersion" | "NoSuchKey")) => {}
+            Err(err) => return Err(err),
+        }
+    }
+
+    if trashed.is_none() {
+        *trashed = Some(Trash::new(config).entries()?);
+    }
+    let contents = trashed
+        .iter()
+        .flatten()
+        .filter(|trashed| trashed.path == entry.path && trashed.size == entry.size)
+        .filter_map(|trashed| trashed.contents().ok())
+        .find(|contents| hash.matches(contents));
+    Ok(contents)
+}
+
+fn write_file(target: &Path, path: &str, contents: &[u8]) -> Result<(), Error> {
+    let file = target.join(path);
+    file.parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&file, contents))
+        .map_err(|source| Error::LocalSyncFailed { path: file, source })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cync::file_viewer::{LocalContents, RemoteContents};
+    use crate::hash::HashAlgorithm;
+
+    fn entry(path: &str, contents: &str) -> ManifestEntry {
+        ManifestEntry {
+            path: path.to_string(),
+            hash: HashAlgorithm::default()
+                .compute(contents.as_bytes())
+                .to_tagged(),
+            size: contents.len() as u64,
+            version_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_restore_finds_every_file_or_writes_nothing() {
+        let directory = tempfile::tempdir().unwrap();
+        let local_directory = directory.path().join("local");
+        let config = Config::for_tests(local_directory.clone());
+        let local = |path: &str, contents: &str| {
+            let file = local_directory.join(path);
+            fs::write(&file, contents).unwrap();
+            (
+                HashAlgorithm::default().compute(contents.as_bytes()),
+                LocalContents::unread(file, contents.len() as u64),
+            )
+        };
+        fs::create_dir_all(&local_directory).unwrap();
+        local("trashed.txt", "gone");
+        Trash::new(&config).put("trashed.txt").unwrap();
+
+        let (same_hash, same) = local("same.txt", "same");
+        let (edited_hash, edited) = local("edited.txt", "new");
+        let (extra_hash, extra) = local("extra.txt", "extra");
+        let files: Files = [
+            (
+                String::from("same.txt"),
+                FileKind::OnlyInLocal {
+                    hash: same_hash,
+                    contents: same,
+                },
+            ),
+            (
+                String::from("edited.txt"),
+                FileKind::ExistsInBoth {
+                    local_hash: edited_hash,
+                    local_contents: edited,
+                    remote_hash: HashAlgorithm::default().compute(b"old"),
+                    remote_contents: RemoteContents::loaded(
+                        String::from("edited.txt"),
+                        String::from("edited.txt"),
+                        b"old".to_vec(),
+                    ),
+                },
+            ),
+            (
+                String::from("extra.txt"),
+                FileKind::OnlyInLocal {
+                    hash: extra_hash,
+                    contents: extra,
+                },
+            ),
+        ]
+        .into_iter()
+        .collect();
+        let mut snapshot = Snapshot {
+            id: String::from("2026-10-18T12-00-00.000Z"),
+            manifest: Manifest {
+                created: String::from("2026-10-18T12:00:00+00:00"),
+                label: None,
+                files: vec![
+                    entry("edited.txt", "old"),
+                    entry("lost.txt", "lost"),
+                    entry("same.txt", "same"),
+                    entry("trashed.txt", "gone"),
+                ],
+            },
+        };
+        let read = |path: &str| fs::read_to_string(local_directory.join(path)).ok();
+
+        let incomplete = restore_files(&config, &snapshot, &files, None).await;
+        assert!(matches!(
+            incomplete,
+            Err(Error::SnapshotIncomplete { missing, .. }) if missing == ["lost.txt"]
+        ));
+        assert_eq!(read("edited.txt").as_deref(), Some("new"));
+        assert_eq!(read("trashed.txt"), None);
+        snapshot.manifest.files.remove(1);
+
+        // A separate target gets every file, copied from wherever it was found.
+        assert!(matches!(
+            check_target(&local_directory),
+            Err(Error::SnapshotTargetNotEmpty { .. })
+        ));
+        let target = directory.path().join("target");
+        check_target(&target).unwrap();
+        let restoration = restore_files(&config, &snapshot, &files, Some(&target))
+            .await
+            .unwrap();
+        assert_eq!(restoration.written, 3);
+        for (path, contents) in [
+            ("edited.txt", "old"),
+            ("same.txt", "same"),
+            ("trashed.txt", "gone"),
+        ] {
+            assert_eq!(fs::read_to_string(target.join(path)).unwrap(), contents);
+        }
+
+        let restoration = restore_files(&config, &snapshot, &files, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            restoration,
+            Restoration {
+                written: 2,
+                unchanged: 1,
+                removed: 1,
+            }
+        );
+        assert_eq!(read("edited.txt").as_deref(), Some("old"));
+        assert_eq!(read("trashed.txt").as_deref(), Some("gone"));
+        assert_eq!(read("extra.txt"), None);
+        let trashed = Trash::new(&config).entries().unwrap();
+        let trashed = trashed
+            .iter()
+            .map(|entry| entry.path.as_str())
+            .collect::<Vec<_>>();
+        assert!(trashed.contains(&"extra.txt"));
+        assert!(trashed.contains(&"edited.txt"));
+    }
+
+    #[test]
+    fn test_manifest_round_trips_through_toml() {
+        let manifest = Manifest {
+            created: String::from("2026-10-18T12:00:00+00:00"),
+            label: Some(String::from("before the move")),
+            files: vec![
+                ManifestEntry {
+                    path: String::from("docs/notes.txt"),
+                    hash: crate::hash::HashAlgorithm::Sha256
+                        .compute(b"notes")
+                        .to_tagged(),
+                    size: 5,
+                    version_id: Some(String::from("3HL4kqtJlcpXroDTDmjVBH40Nrjfkd")),
+                },
+                ManifestEntry {
+                    path: String::from("draft.md"),
+                    hash: crate::hash::HashAlgorithm::Sha256.compute(b"").to_tagged(),
+                    size: 0,
+                    version_id: None,
+                },
+            ],
+        };
+        let text = toml::to_string(&manifest).unwrap();
+        assert_eq!(toml::from_str::<Manifest>(&text).unwrap(), manifest);
+
+        let snapshot = Snapshot {
+            id: String::from("2026-10-18T12-00-00.000Z"),
+            manifest,
+        };
+        assert_eq!(
+            snapshot.to_string(),
+            "2026-10-18T12-00-00.000Z  2 files (1 not versioned)  before the move"
+        );
+        assert!(is_snapshot(&manifest_path(&snapshot.id)));
+        let taken = chrono::DateTime::parse_from_rfc3339("2026-10-18T12:00:00.250+00:00").unwrap();
+        assert_eq!(
+            taken.format(ID_FORMAT).to_string(),
+            "2026-10-18T12-00-00.250Z"
+        );
+        assert!(!is_snapshot(".cync-snapshots.txt"));
+    }
+}
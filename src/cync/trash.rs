@@ -0,0 +1,502 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{Local, NaiveDateTime, TimeDelta};
+use ratatui::widgets::TableState;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use super::config::Config;
+use super::{Cync, FilePath, Mode, Operation};
+use crate::error::Error;
+
+/// The folder inside the local directory that holds trashed files, skipped when syncing.
+pub const TRASH_DIRECTORY: &str = ".cync-trash";
+
+// Names of the folders in `TRASH_DIRECTORY`, one per time something was trashed.
+const STAMP_FORMAT: &str = "%Y-%m-%dT%H-%M-%S%.3f";
+const STAMP_LENGTH: usize = 23;
+// The format of `DeletionDate` in the XDG trash.
+const DELETION_DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrashLocation {
+    /// `.cync-trash/<timestamp>/` inside the local directory.
+    #[default]
+    Local,
+    /// The desktop trash, usually `~/.local/share/Trash`.
+    Xdg,
+}
+
+/// The `[trash]` section of the config file. A retention of zero keeps files until the trash
+/// is emptied.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrashConfig {
+    pub location: TrashLocation,
+    pub retention_days: u32,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        TrashConfig {
+            location: TrashLocation::default(),
+            retention_days: 30,
+        }
+    }
+}
+
+/// A local file that was overwritten or deleted.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrashEntry {
+    pub path: FilePath,
+    pub trashed_at: NaiveDateTime,
+    pub size: u64,
+    /// Where the file sits in the trash.
+    file: PathBuf,
+    /// The `.trashinfo` file describing it, in the XDG trash.
+    info: Option<PathBuf>,
+}
+
+impl TrashEntry {
+    pub fn contents(&self) -> io::Result<Vec<u8>> {
+        fs::read(&self.file)
+    }
+}
+
+/// Where local files go instead of being overwritten or removed.
+pub struct Trash {
+    local_directory: PathBuf,
+    /// The root of the XDG trash, or `None` for the one inside the local directory.
+    xdg: Option<PathBuf>,
+    retention_days: u32,
+}
+
+impl Trash {
+    pub fn new(config: &Config) -> Self {
+        let xdg = match config.trash().location {
+            TrashLocation::Local => None,
+            TrashLocation::Xdg => match xdg::BaseDirectories::new() {
+                Ok(directories) => Some(directories.get_data_home().join("Trash")),
+                Err(err) => {
+                    warn!("Could not locate the XDG trash, using `{TRASH_DIRECTORY}`: {err}");
+                    None
+                }
+            },
+        };
+        Trash {
+            local_directory: config.local_directory().clone(),
+            xdg,
+            retention_days: config.trash().retention_days,
+        }
+    }
+
+    fn root(&self) -> PathBuf {
+        match &self.xdg {
+            Some(root) => root.clone(),
+            None => self.local_directory.join(TRASH_DIRECTORY),
+        }
+    }
+
+    /// Moves the local copy of `path` into the trash, if there is one.
+    pub fn put(&self, path: &str) -> Result<(), Error> {
+        let source = self.local_directory.join(path);
+        if !source.is_file() {
+            return Ok(());
+        }
+        let now = Local::now().naive_local();
+        match &self.xdg {
+            Some(root) => put_xdg(root, &source, now),
+            None => self.put_local(path, &source, now),
+        }
+        .map_err(|err| Error::Trash {
+            path: source.clone(),
+            source: err,
+        })?;
+        info!("Moved `{path}` to the trash");
+        Ok(())
+    }
+
+    fn put_local(&self, path: &str, source: &Path, now: NaiveDateTime) -> io::Result<()> {
+        let stamp = now.format(STAMP_FORMAT).to_string();
+        let root = self.root();
+        // Two files trashed under the same path in the same millisecond get their own folders.
+        let target = (0..)
+            .map(|n| match n {
+                0 => root.join(&stamp).join(path),
+                n => root.join(format!("{stamp}.{n}")).join(path),
+            })
+            .find(|target| !target.exists())
+            .expect("some folder name is free");
+        fs::create_dir_all(target.parent().expect("trashed files live in a folder"))?;
+        move_file(source, &target)
+    }
+
+    /// Every trashed file of this local directory, newest first.
+    pub fn entries(&self) -> Result<Vec<TrashEntry>, Error> {
+        let root = self.root();
+        let mut entries = match &self.xdg {
+            Some(_) => self.xdg_entries(&root),
+            None => local_entries(&root),
+        }
+        .or_else(|err| match err.kind() {
+            io::ErrorKind::NotFound => Ok(Vec::new()),
+            _ => Err(err),
+        })
+        .map_err(|source| Error::LoadingTrash {
+            path: root.clone(),
+            source,
+        })?;
+        entries.sort_by(|a, b| b.trashed_at.cmp(&a.trashed_at).then(a.path.cmp(&b.path)));
+        Ok(entries)
+    }
+
+    fn xdg_entries(&self, root: &Path) -> io::Result<Vec<TrashEntry>> {
+        let mut entries = Vec::new();
+        for info in fs::read_dir(root.join("info"))? {
+            let info = info?.path();
+            let Some(name) = info
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(".trashinfo"))
+            else {
+                continue;
+            };
+            let Some((original, trashed_at)) = parse_trash_info(&fs::read_to_string(&info)?) else {
+                continue;
+            };
+            // The desktop trash is shared, only files from the local directory are ours.
+            let Ok(path) = original.strip_prefix(&self.local_directory) else {
+                continue;
+            };
+            let file = root.join("files").join(name);
+            let Ok(metadata) = fs::metadata(&file) else {
+                continue;
+            };
+            entries.push(TrashEntry {
+                path: path.to_string_lossy().into_owned(),
+                trashed_at,
+                size: metadata.len(),
+                file,
+                info: Some(info),
+            });
+        }
+        Ok(entries)
+    }
+
+    /// Moves `entry` back to where it was, trashing whatever has taken its place since.
+    pub fn restore(&self, entry: &TrashEntry) -> Result<(), Error> {
+        self.put(&entry.path)?;
+        let destination = self.local_directory.join(&entry.path);
+        destination
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| move_file(&entry.file, &destination))
+            .map_err(|source| Error::TrashRestore {
+                path: entry.path.clone(),
+                source,
+            })?;
+        self.forget(entry);
+        info!("Restored `{}` from the trash", entry.path);
+        Ok(())
+    }
+
+    /// Deletes `entry` for good.
+    fn remove(&self, entry: &TrashEntry) -> Result<(), Error> {
+        fs::remove_file(&entry.file).map_err(|source| Error::LocalDeleteFailed {
+            path: entry.file.clone(),
+            source,
+        })?;
+        self.forget(entry);
+        Ok(())
+    }
+
+    /// Cleans up what is left of `entry` once its file has been moved or removed.
+    fn forget(&self, entry: &TrashEntry) {
+        let result = match &entry.info {
+            Some(info) => fs::remove_file(info),
+            None => {
+                // Folders emptied by taking out their last file go too.
+                let root = self.root();
+                let mut result = Ok(());
+                for folder in entry.file.ancestors().skip(1) {
+                    if folder == root || !folder.starts_with(&root) {
+                        break;
+                    }
+                    result = fs::remove_dir(folder);
+                    if result.is_err() {
+                        break;
+                    }
+                }
+                match result {
+                    Err(err) if err.kind() == io::ErrorKind::DirectoryNotEmpty => Ok(()),
+                    result => result,
+                }
+            }
+        };
+        if let Err(err) = result {
+            warn!("Failed to clean up the trash after `{}`: {err}", entry.path);
+        }
+    }
+
+    /// Deletes every trashed file and returns how many there were.
+    pub fn empty(&self) -> Result<usize, Error> {
+        let entries = self.entries()?;
+        for entry in &entries {
+            self.remove(entry)?;
+        }
+        Ok(entries.len())
+    }
+
+    /// Deletes the files that have been in the trash for longer than the retention period.
+    pub fn expire(&self) -> Result<usize, Error> {
+        if self.retention_days == 0 {
+            return Ok(0);
+        }
+        let cutoff = Local::now().naive_local() - TimeDelta::days(self.retention_days.into());
+        let expired = self
+            .entries()?
+            .into_iter()
+            .filter(|entry| entry.trashed_at < cutoff)
+            .collect::<Vec<_>>();
+        for entry in &expired {
+            self.remove(entry)?;
+        }
+        Ok(expired.len())
+    }
+}
+
+fn local_entries(root: &Path) -> io::Result<Vec<TrashEntry>> {
+    let mut entries = Vec::new();
+    for folder in fs::read_dir(root)? {
+        let folder = folder?.path();
+        let trashed_at = folder
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.get(..STAMP_LENGTH))
+            .and_then(|stamp| NaiveDateTime::parse_from_str(stamp, STAMP_FORMAT).ok());
+        let Some(trashed_at) = trashed_at.filter(|_| folder.is_dir()) else {
+            continue;
+        };
+        for (file, size) in files_in(&folder)? {
+            let path = file
+                .strip_prefix(&folder)
+                .expect("walked files are inside the folder");
+            entries.push(TrashEntry {
+                path: path.to_string_lossy().into_owned(),
+                trashed_at,
+                size,
+                file,
+                info: None,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+fn files_in(folder: &Path) -> io::Result<Vec<(PathBuf, u64)>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(folder)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            files.extend(files_in(&entry.path())?);
+        } else {
+            files.push((entry.path(), metadata.len()));
+        }
+    }
+    Ok(files)
+}
+
+/// Trashes `source` the way desktop file managers do, so it shows up in theirs as well.
+fn put_xdg(root: &Path, source: &Path, now: NaiveDateTime) -> io::Result<()> {
+    let files = root.join("files");
+    let infos = root.join("info");
+    fs::create_dir_all(&files)?;
+    fs::create_dir_all(&infos)?;
+    let file_name = source
+        .file_name()
+        .expect("trashed files have a name")
+        .to_string_lossy();
+    let contents = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        encode_path(source),
+        now.format(DELETION_DATE_FORMAT)
+    );
+    // Creating the info file claims the name, as the spec asks.
+    for n in 0.. {
+        let name = match n {
+            0 => file_name.to_string(),
+            n => format!("{file_name}.{n}"),
+        };
+        let info = infos.join(format!("{name}.trashinfo"));
+        let mut claimed = match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info)
+        {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        };
+        let result = claimed
+            .write_all(contents.as_bytes())
+            .and_then(|_| move_file(source, &files.join(&name)));
+        if result.is_err() {
+            let _ = fs::remove_file(&info);
+        }
+        return result;
+    }
+    unreachable!("some name is free")
+}
+
+/// Renames `from` to `to`, or copies it across when they are on different file systems.
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    fs::rename(from, to).or_else(|_| fs::copy(from, to).and_then(|_| fs::remove_file(from)))
+}
+
+fn parse_trash_info(contents: &str) -> Option<(PathBuf, NaiveDateTime)> {
+    let field = |name: &str| {
+        contents
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix('='))
+    };
+    let path = decode_path(field("Path")?)?;
+    let trashed_at = NaiveDateTime::parse_from_str(field("DeletionDate")?, DELETION_DATE_FORMAT);
+    Some((path, trashed_at.ok()?))
+}
+
+/// Percent-encodes `path` for a `.trashinfo` file.
+fn encode_path(path: &Path) -> String {
+    let mut encoded = String::new();
+    for &byte in path.to_string_lossy().as_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            byte => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+fn decode_path(encoded: &str) -> Option<PathBuf> {
+    let mut bytes = Vec::new();
+    let mut rest = encoded.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+/// The trashed files listed in the trash pane.
+pub struct TrashView {
+    pub entries: Vec<TrashEntry>,
+    pub state: TableState,
+}
+
+impl TrashView {
+    pub fn selected(&self) -> Option<&TrashEntry> {
+        self.state.selected().and_then(|i| self.entries.get(i))
+    }
+
+    pub fn move_by(&mut self, delta: isize) {
+        let last = self.entries.len().saturating_sub(1);
+        let i = self
+            .state
+            .selected()
+            .unwrap_or_default()
+            .saturating_add_signed(delta)
+            .min(last);
+        self.state.select(Some(i));
+    }
+}
+
+impl Cync {
+    /// Opens the trash pane with every file overwritten or deleted locally.
+    pub fn open_trash(&mut self) -> Result<(), Error> {
+        match Trash::new(&self.config).entries() {
+            Ok(entries) => {
+                let selected = (!entries.is_empty()).then_some(0);
+                self.trash = Some(TrashView {
+                    entries,
+                    state: TableState::default().with_selected(selected),
+                });
+                self.mode = Mode::Trash;
+                Ok(())
+            }
+            Err(err) => self.report(err),
+        }
+    }
+
+    pub fn close_trash(&mut self) {
+        self.trash = None;
+        self.mode = Mode::Default;
+    }
+
+    /// Puts the selected file back in the local directory.
+    pub async fn restore_selected_trash(&mut self) -> Result<(), Error> {
+        let Some(entry) = self.trash.as_ref().and_then(TrashView::selected).cloned() else {
+            return Ok(());
+        };
+        self.trash = None;
+        self.perform(Operation::RestoreFromTrash(entry)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trashed_files_can_be_restored() {
+        let directory = tempfile::tempdir().unwrap();
+        let local_directory = directory.path().to_path_buf();
+        fs::create_dir_all(local_directory.join("docs")).unwrap();
+        fs::write(local_directory.join("docs/notes.txt"), "first").unwrap();
+        let trash = Trash {
+            local_directory: local_directory.clone(),
+            xdg: None,
+            retention_days: 30,
+        };
+
+        trash.put("docs/notes.txt").unwrap();
+        assert!(!local_directory.join("docs/notes.txt").exists());
+        fs::write(local_directory.join("docs/notes.txt"), "second").unwrap();
+
+        let entries = trash.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].path, "docs/notes.txt");
+        assert_eq!(entries[0].size, 5);
+
+        // The file restored over is trashed in turn, and the emptied folder is removed.
+        trash.restore(&entries[0]).unwrap();
+        let contents = fs::read_to_string(local_directory.join("docs/notes.txt")).unwrap();
+        assert_eq!(contents, "first");
+        let entries = trash.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].size, 6);
+        assert_eq!(trash.empty().unwrap(), 1);
+        assert_eq!(
+            fs::read_dir(local_directory.join(TRASH_DIRECTORY))
+                .unwrap()
+                .count(),
+            0
+        );
+
+        let path = Path::new("/home/me/My Notes/100%.txt");
+        assert_eq!(encode_path(path), "/home/me/My%20Notes/100%25.txt");
+        assert_eq!(decode_path(&encode_path(path)).as_deref(), Some(path));
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::{BTreeMap, HashSet};
+
+use super::file_viewer::{FileKind, Files};
+use super::FilePath;
+
+/// One row of the directory tree shown in the TUI.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TreeNode {
+    Folder {
+        path: String,
+        name: String,
+        depth: usize,
+        collapsed: bool,
+        files: usize,
+        out_of_sync: usize,
+    },
+    File {
+        path: FilePath,
+        name: String,
+        depth: usize,
+    },
+}
+
+impl TreeNode {
+    pub fn path(&self) -> &str {
+        match self {
+            TreeNode::Folder { path, .. } | TreeNode::File { path, .. } => path,
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        match self {
+            TreeNode::Folder { depth, .. } | TreeNode::File { depth, .. } => *depth,
+        }
+    }
+
+    /// The name of the node, indented by its depth and followed by the folder summary.
+    pub fn label(&self) -> String {
+        let indent = "  ".repeat(self.depth());
+        match self {
+            TreeNode::Folder {
+                name,
+                collapsed,
+                files,
+                out_of_sync,
+                ..
+            } => {
+                let marker = if *collapsed { "▸" } else { "▾" };
+                let summary = if *out_of_sync == 0 {
+                    format!("{files} in sync")
+                } else {
+                    format!("{out_of_sync} out of sync")
+                };
+                format!("{indent}{marker} {name}/ ({summary})")
+            }
+            TreeNode::File { name, .. } => format!("{indent}  {name}"),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Directory {
+    folders: BTreeMap<String, Directory>,
+    files: Vec<(String, FilePath)>,
+    total: usize,
+    out_of_sync: usize,
+}
+
+impl Directory {
+    fn insert(&mut self, path: &FilePath, kind: &FileKind) {
+        let mut directory = self;
+        let mut segments = path.split('/').peekable();
+        while let Some(segment) = segments.next() {
+            directory.total += 1;
+            if !kind.is_in_sync() {
+                directory.out_of_sync += 1;
+            }
+            if segments.peek().is_none() {
+                directory.files.push((segment.to_string(), path.clone()));
+            } else {
+                directory = directory.folders.entry(segment.to_string()).or_default();
+            }
+        }
+    }
+
+    fn flatten(
+        &self,
+        prefix: &str,
+        depth: usize,
+        collapsed: &HashSet<String>,
+        nodes: &mut Vec<TreeNode>,
+    ) {
+        for (name, directory) in &self.folders {
+            let path = format!("{prefix}{name}");
+            let is_collapsed = collapsed.contains(&path);
+            nodes.push(TreeNode::Folder {
+                path: path.clone(),
+                name: name.clone(),
+                depth,
+                collapsed: is_collapsed,
+                files: directory.total,
+                out_of_sync: directory.out_of_sync,
+            });
+            if !is_collapsed {
+                directory.flatten(&format!("{path}/"), depth + 1, collapsed, nodes);
+            }
+        }
+        nodes.extend(self.files.iter().map(|(name, path)| TreeNode::File {
+            path: path.clone(),
+            name: name.clone(),
+            depth,
+        }));
+    }
+}
+
+/// Lays out `files` as a tree, folders before files, skipping the contents of `collapsed`
+/// folders.
+pub fn build_tree<'a>(
+    files: impl IntoIterator<Item = (&'a FilePath, &'a FileKind)>,
+    collapsed: &HashSet<String>,
+) -> Vec<TreeNode> {
+    let mut root = Directory::default();
+    for (path, kind) in files {
+        root.insert(path, kind);
+    }
+
+    let mut nodes = Vec::new();
+    root.flatten("", 0, collapsed, &mut nodes);
+    nodes
+}
+
+/// Every file under `folder`, at any depth.
+pub fn files_in_folder<'a>(
+    files: &'a Files,
+    folder: &str,
+) -> impl Iterator<Item = (&'a FilePath, &'a FileKind)> {
+    let prefix = format!("{folder}/");
+    files
+        .range(prefix.clone()..)
+        .take_while(move |(path, _)| path.starts_with(&prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::{
+        cync::file_viewer::{LocalContents, RemoteContents},
+        hash::HashAlgorithm,
+    };
+
+    fn local(contents: &str) -> FileKind {
+        FileKind::OnlyInLocal {
+            hash: HashAlgorithm::default().compute(contents.as_bytes()),
+            contents: LocalContents::loaded(PathBuf::from(contents), contents.as_bytes().to_vec()),
+        }
+    }
+
+    fn synced(contents: &str) -> FileKind {
+        FileKind::ExistsInBoth {
+            local_hash: HashAlgorithm::default().compute(contents.as_bytes()),
+            local_contents: LocalContents::loaded(
+                PathBuf::from(contents),
+                contents.as_bytes().to_vec(),
+            ),
+            remote_hash: HashAlgorithm::default().compute(contents.as_bytes()),
+            remote_contents: RemoteContents::loaded(
+                contents.to_string(),
+                contents.to_string(),
+                contents.as_bytes().to_vec(),
+            ),
+        }
+    }
+
+    #[test]
+    fn test_build_tree() {
+        let files: Files = vec![
+            (String::from("a/b/file1"), local("file1")),
+            (String::from("a/file2"), synced("file2")),
+            (String::from("file3"), synced("file3")),
+        ]
+        .into_iter()
+        .collect();
+
+        let paths = |nodes: Vec<TreeNode>| {
+            nodes
+                .iter()
+                .map(|node| node.path().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let nodes = build_tree(&files, &HashSet::new());
+        assert!(matches!(
+            nodes[0],
+            TreeNode::Folder {
+                files: 2,
+                out_of_sync: 1,
+                ..
+            }
+        ));
+        assert_eq!(
+            paths(nodes),
+            vec!["a", "a/b", "a/b/file1", "a/file2", "file3"]
+        );
+
+        let collapsed = HashSet::from([String::from("a")]);
+        assert_eq!(paths(build_tree(&files, &collapsed)), vec!["a", "file3"]);
+    }
+}
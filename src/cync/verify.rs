@@ -0,0 +1,507 @@
+use std::{collections::HashMap, fmt, fs, path::Path};
+
+use tracing::warn;
+
+use super::base::SyncBase;
+use super::config::Config;
+use super::file_viewer::{FileViewer, LocalFile};
+use super::hash_cache::HashCache;
+use super::{codec, FileContents, FileHash, FilePath};
+use crate::error::Error;
+use crate::hash::S3Checksum;
+use crate::s3::RemoteObject;
+use crate::util::walk_directory;
+
+/// What `cync verify` found wrong with a file.
+#[derive(Debug, PartialEq)]
+pub enum Problem {
+    /// The local contents changed while size, mtime and inode stayed the same, so the hash
+    /// cache still held the old hash.
+    LocalChangedUnnoticed,
+    /// The object is not as long as what was uploaded.
+    Truncated { expected: u64, actual: u64 },
+    /// The object does not match S3's checksum or the hash recorded on upload.
+    Corrupted { expected: String, actual: String },
+    /// The object was overwritten outside of Cync, which kept the metadata of the last upload.
+    ModifiedOutOfBand,
+    /// The object could not be read back at all.
+    Unreadable(String),
+    /// A version kept for merging is left over from a file that is gone from both sides.
+    StaleBase,
+    /// The version kept for merging differs from the file, though both sides agree on it.
+    BaseDisagrees,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::LocalChangedUnnoticed => write!(
+                f,
+                "local contents changed without a new modification time, from bit rot or a write that kept the old one"
+            ),
+            Problem::Truncated { expected, actual } => write!(
+                f,
+                "remote object is truncated, {actual} of {expected} bytes are there"
+            ),
+            Problem::Corrupted { expected, actual } => write!(
+                f,
+                "remote object is corrupted, expected hash {expected} but got {actual}"
+            ),
+            Problem::ModifiedOutOfBand => write!(f, "remote object was replaced outside of Cync"),
+            Problem::Unreadable(reason) => write!(f, "remote object could not be read: {reason}"),
+            Problem::StaleBase => write!(f, "synced version is kept for a file that is gone"),
+            Problem::BaseDisagrees => write!(
+                f,
+                "synced version differs from the file both sides agree on"
+            ),
+        }
+    }
+}
+
+pub struct Finding {
+    pub path: FilePath,
+    pub problem: Problem,
+    pub repaired: bool,
+}
+
+/// The outcome of `cync verify`.
+pub struct Verification {
+    pub local_files: usize,
+    pub remote_objects: usize,
+    pub findings: Vec<Finding>,
+}
+
+impl Verification {
+    pub fn unrepaired(&self) -> usize {
+        self.findings.iter().filter(|f| !f.repaired).count()
+    }
+}
+
+impl fmt::Display for Verification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for finding in &self.findings {
+            let repaired = if finding.repaired { " (repaired)" } else { "" };
+            writeln!(f, "`{}`: {}{repaired}", finding.path, finding.problem)?;
+        }
+        write!(
+            f,
+            "Checked {} local files and {} remote objects, found {} problems",
+            self.local_files,
+            self.remote_objects,
+            self.findings.len()
+        )
+    }
+}
+
+/// What was learnt about one object on the remote.
+struct RemoteCheck {
+    problem: Option<Problem>,
+    /// The hash of the file recorded on upload.
+    stored: Option<FileHash>,
+    /// The decoded contents, if they were downloaded and found intact.
+    contents: Option<FileContents>,
+}
+
+/// Hashes every local file again and checks every remote object, downloading them or, with
+/// `quick`, only reading their metadata. With `repair`, damaged files are replaced from the
+/// other side when it holds the version that was last synced.
+pub async fn verify(config: &Config, quick: bool, repair: bool) -> Result<Verification, Error> {
+    let local_directory = config.local_directory();
+    let stored_hashes = HashCache::load(config.remote_directory(), config.hash_algorithm());
+    let mut hashes = HashCache::rebuild(config.remote_directory(), config.hash_algorithm());
+    let local = walk_directory(local_directory, local_directory, &mut hashes)?;
+    let unnoticed = unnoticed_changes(local_directory, &local, &stored_hashes);
+
+    let objects = FileViewer::list_remote(config).await?;
+    let mut remote = HashMap::new();
+    for (path, object) in &objects {
+        let key = object.key().expect("uploaded objects must have a key");
+        let check = if quick {
+            check_metadata(config, path, key).await?
+        } else {
+            check_contents(config, path, key).await?
+        };
+        remote.insert(path.clone(), check);
+    }
+
+    let mut base = SyncBase::new(config.remote_directory(), config.hash_algorithm());
+    let mut findings = Vec::new();
+    for (path, cached) in unnoticed {
+        // The remote copy is the good one if it is what the file hashed to before.
+        let good = remote
+            .get(&path)
+            .and_then(|check| check.contents.as_ref())
+            .filter(|contents| cached.matches(contents));
+        let repaired = match good {
+            Some(contents) if repair => {
+                let current = local.get(&path).map(|(hash, _)| *hash);
+                config.write_local_file(&path, contents, current)?;
+                true
+            }
+            _ => false,
+        };
+        findings.push(Finding {
+            path,
+            problem: Problem::LocalChangedUnnoticed,
+            repaired,
+        });
+    }
+    for (path, problem) in check_base(&mut base, &local, &remote) {
+        let repaired = repair
+            && match (&problem, local.get(&path)) {
+                (Problem::BaseDisagrees, Some((hash, contents))) => {
+                    base.record(&path, *hash, contents.get()?).is_ok()
+                }
+                _ => {
+                    base.forget(&path);
+                    true
+                }
+            };
+        findings.push(Finding {
+            path,
+            problem,
+            repaired,
+        });
+    }
+    for (path, check) in remote {
+        let Some(problem) = check.problem else {
+            continue;
+        };
+        let good = local
+            .get(&path)
+            .filter(|local| local_is_good(&path, local, check.stored, &mut base))
+            .map(|(_, contents)| contents);
+        let repaired = match good {
+            Some(contents) if repair && problem != Problem::ModifiedOutOfBand => {
+                config.upload(&path, contents.get()?).await?;
+                true
+            }
+            _ => false,
+        };
+        findings.push(Finding {
+            path,
+            problem,
+            repaired,
+        });
+    }
+    findings.sort_by(|a, b| a.path.cmp(&b.path));
+
+    if repair {
+        if let Err(err) = hashes.save() {
+            warn!("Failed to save the rebuilt hash cache: {err}");
+        }
+    }
+    Ok(Verification {
+        local_files: local.len(),
+        remote_objects: objects.len(),
+        findings,
+    })
+}
+
+/// Local files the cache still holds an outdated hash for, with that hash.
+fn unnoticed_changes(
+    local_directory: &Path,
+    local: &HashMap<FilePath, LocalFile>,
+    stored_hashes: &HashCache,
+) -> HashMap<FilePath, FileHash> {
+    let mut unnoticed = HashMap::new();
+    for (path, (hash, _)) in local {
+        let cached = fs::metadata(local_directory.join(path))
+            .ok()
+            .and_then(|metadata| stored_hashes.cached(path, &metadata));
+        if let Some(cached) = cached.filter(|cached| cached != hash) {
+            unnoticed.insert(path.clone(), cached);
+        }
+    }
+    unnoticed
+}
+
+/// Whether the local copy of a damaged object is the good one: it is what was uploaded, or
+/// what was last synced. Legacy MD5s are checked against the contents themselves.
+fn local_is_good(
+    path: &str,
+    (hash, contents): &LocalFile,
+    stored: Option<FileHash>,
+    base: &mut SyncBase,
+) -> bool {
+    let uploaded = stored.is_some_and(|stored| {
+        if stored.algorithm() == hash.algorithm() {
+            stored == *hash
+        } else {
+            contents
+                .get()
+                .is_ok_and(|contents| stored.matches(contents))
+        }
+    });
+    uploaded || base.matches(path, hash)
+}
+
+/// Versions kept for merging that are left over, or that disagree with a file both sides hold
+/// as recorded on upload.
+fn check_base(
+    base: &mut SyncBase,
+    local: &HashMap<FilePath, LocalFile>,
+    remote: &HashMap<FilePath, RemoteCheck>,
+) -> Vec<(FilePath, Problem)> {
+    let mut problems = Vec::new();
+    for path in base.paths() {
+        let local_hash = local.get(&path).map(|(hash, _)| hash);
+        let problem = match (local_hash, remote.get(&path)) {
+            (None, None) => Problem::StaleBase,
+            (Some(hash), Some(check))
+                if check.problem.is_none()
+                    && check.stored == Some(*hash)
+                    && !base.matches(&path, hash) =>
+            {
+                Problem::BaseDisagrees
+            }
+            _ => continue,
+        };
+        problems.push((path, problem));
+    }
+    problems
+}
+
+/// Downloads the object and runs it through the same checks as loading does.
+async fn check_contents(config: &Config, path: &str, key: &str) -> Result<RemoteCheck, Error> {
+    let download = config
+        .aws_client()
+        .download_object(config.remote_directory().to_string(), key.to_string())
+        .await?;
+    check_object(config, path, download)
+}
+
+fn check_object(config: &Config, path: &str, download: RemoteObject) -> Result<RemoteCheck, Error> {
+    let stored = codec::stored_hash(&download.metadata);
+    let transformed = codec::is_transformed(&download.metadata);
+    let (problem, contents) = match codec::decode(config, path, download) {
+        // Untransformed objects are only held to S3's checksum on load, a stale recorded hash
+        // means the bytes were swapped out from under it.
+        Ok((_, contents)) if !transformed && stored.is_some_and(|s| !s.matches(&contents)) => {
+            (Some(Problem::ModifiedOutOfBand), None)
+        }
+        Ok((_, contents)) => (None, Some(contents)),
+        Err(Error::Truncated {
+            expected, actual, ..
+        }) => (Some(Problem::Truncated { expected, actual }), None),
+        Err(Error::Corrupted {
+            expected, actual, ..
+        }) => (Some(Problem::Corrupted { expected, actual }), None),
+        Err(err @ (Error::Remote(_) | Error::Tui { .. })) => return Err(err),
+        Err(err) => (Some(Problem::Unreadable(err.to_string())), None),
+    };
+    Ok(RemoteCheck {
+        problem,
+        stored,
+        contents,
+    })
+}
+
+/// Checks what can be told from the object's metadata alone: the length of transformed objects,
+/// and for untransformed ones whether S3's SHA-256 still agrees with the hash recorded on upload.
+async fn check_metadata(config: &Config, path: &str, key: &str) -> Result<RemoteCheck, Error> {
+    let head = config
+        .aws_client()
+        .head_object(config.remote_directory().to_string(), key.to_string())
+        .await?;
+    let metadata = head.metadata().cloned().unwrap_or_default();
+    let stored = codec::stored_hash(&metadata);
+    let length = head.content_length().unwrap_or_default().max(0) as u64;
+    let checksum = S3Checksum::from_response(head.checksum_sha256(), head.checksum_crc32_c())
+        .and_then(|checksum| checksum.to_hash());
+    let problem = if codec::is_transformed(&metadata) {
+        match codec::check_size(path, &metadata, length) {
+            Err(Error::Truncated {
+                expected, actual, ..
+            }) => Some(Problem::Truncated { expected, actual }),
+            _ => None,
+        }
+    } else {
+        match (stored, checksum) {
+            (Some(stored), Some(checksum))
+                if stored.algorithm() == checksum.algorithm() && stored != checksum =>
+            {
+                Some(Problem::ModifiedOutOfBand)
+            }
+            _ => None,
+        }
+    };
+    Ok(RemoteCheck {
+        problem,
+        stored,
+        contents: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::{
+        compression::CompressionConfig, cync::file_viewer::LocalContents, hash::HashAlgorithm,
+    };
+
+    fn local_file(contents: &str) -> LocalFile {
+        (
+            HashAlgorithm::default().compute(contents.as_bytes()),
+            LocalContents::loaded(PathBuf::from("notes.txt"), contents.as_bytes().to_vec()),
+        )
+    }
+
+    #[test]
+    fn test_check_object_classifies_damage() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut config = Config::for_tests(directory.path().to_path_buf());
+        let (object, metadata, checksum) = codec::encode(&config, "notes.txt", b"hello");
+        let check = |contents: &[u8], checksum: S3Checksum, config: &Config| {
+            let object = RemoteObject {
+                contents: contents.to_vec(),
+                metadata: metadata.clone(),
+                checksum: Some(checksum),
+            };
+            check_object(config, "notes.txt", object).unwrap()
+        };
+
+        let intact = check(&object, checksum.clone(), &config);
+        assert_eq!(intact.problem, None);
+        assert_eq!(intact.contents.as_deref(), Some(b"hello".as_slice()));
+
+        let corrupted = check(b"hellp", checksum, &config);
+        assert!(matches!(corrupted.problem, Some(Problem::Corrupted { .. })));
+
+        // S3's checksum follows the new bytes, only the hash recorded on upload is stale.
+        let replaced = S3Checksum::new(config.hash_algorithm(), b"howdy");
+        let replaced = check(b"howdy", replaced, &config);
+        assert_eq!(replaced.problem, Some(Problem::ModifiedOutOfBand));
+        assert_eq!(
+            replaced.stored,
+            Some(config.hash_algorithm().compute(b"hello"))
+        );
+
+        config.compression = Some(CompressionConfig::default());
+        let contents = "hello ".repeat(100);
+        let (object, metadata, _) = codec::encode(&config, "notes.txt", contents.as_bytes());
+        let truncated = RemoteObject {
+            contents: object[..object.len() / 2].to_vec(),
+            metadata,
+            checksum: None,
+        };
+        let truncated = check_object(&config, "notes.txt", truncated).unwrap();
+        assert_eq!(
+            truncated.problem,
+            Some(Problem::Truncated {
+                expected: object.len() as u64,
+                actual: (object.len() / 2) as u64,
+            })
+        );
+    }
+
+    #[test]
+    fn test_local_copy_is_good_when_uploaded_or_last_synced() {
+        let directory = tempfile::tempdir().unwrap();
+        fs::create_dir_all(directory.path().join("base")).unwrap();
+        fs::write(directory.path().join("base/notes.txt"), "synced").unwrap();
+        let algorithm = HashAlgorithm::default();
+        let mut base = SyncBase::open(
+            directory.path().join("base"),
+            HashCache::open(directory.path().join("base-hashes"), algorithm),
+        );
+
+        let edited = local_file("edited");
+        let uploaded = Some(algorithm.compute(b"edited"));
+        assert!(local_is_good("notes.txt", &edited, uploaded, &mut base));
+        let legacy = Some(HashAlgorithm::Md5.compute(b"edited"));
+        assert!(local_is_good("notes.txt", &edited, legacy, &mut base));
+        let other = Some(algorithm.compute(b"other"));
+        assert!(!local_is_good("notes.txt", &edited, other, &mut base));
+        assert!(!local_is_good("notes.txt", &edited, None, &mut base));
+        assert!(local_is_good(
+            "notes.txt",
+            &local_file("synced"),
+            other,
+            &mut base
+        ));
+
+        let local = HashMap::from([(String::from("notes.txt"), edited)]);
+        let remote = HashMap::from([(
+            String::from("notes.txt"),
+            RemoteCheck {
+                problem: None,
+                stored: uploaded,
+                contents: None,
+            },
+        )]);
+        assert_eq!(
+            check_base(&mut base, &local, &remote),
+            vec![(String::from("notes.txt"), Problem::BaseDisagrees)]
+        );
+        assert_eq!(
+            check_base(&mut base, &HashMap::new(), &HashMap::new()),
+            vec![(String::from("notes.txt"), Problem::StaleBase)]
+        );
+    }
+
+    #[test]
+    fn test_unnoticed_changes_come_from_stale_cache_stamps() {
+        let directory = tempfile::tempdir().unwrap();
+        let local_directory = directory.path().join("local");
+        fs::create_dir_all(&local_directory).unwrap();
+        fs::write(local_directory.join("notes.txt"), "after").unwrap();
+        fs::write(local_directory.join("todo.txt"), "same").unwrap();
+        let algorithm = HashAlgorithm::default();
+
+        // The cache holds an older hash under the stamp the file still has.
+        let cache_path = directory.path().join("hashes");
+        let mut cache = HashCache::open(cache_path.clone(), algorithm);
+        for (path, contents) in [("notes.txt", "before"), ("todo.txt", "same")] {
+            let metadata = fs::metadata(local_directory.join(path)).unwrap();
+            cache.record(path, &metadata, algorithm.compute(contents.as_bytes()));
+        }
+        cache.save().unwrap();
+
+        let local = HashMap::from([
+            (String::from("notes.txt"), local_file("after")),
+            (String::from("todo.txt"), local_file("same")),
+        ]);
+        let unnoticed = unnoticed_changes(
+            &local_directory,
+            &local,
+            &HashCache::open(cache_path, algorithm),
+        );
+        assert_eq!(
+            unnoticed,
+            HashMap::from([(String::from("notes.txt"), algorithm.compute(b"before"))])
+        );
+    }
+
+    #[test]
+    fn test_report_counts_unrepaired_findings() {
+        let verification = Verification {
+            local_files: 3,
+            remote_objects: 2,
+            findings: vec![
+                Finding {
+                    path: String::from("notes.txt"),
+                    problem: Problem::Truncated {
+                        expected: 10,
+                        actual: 4,
+                    },
+                    repaired: true,
+                },
+                Finding {
+                    path: String::from("photo.jpg"),
+                    problem: Problem::ModifiedOutOfBand,
+                    repaired: false,
+                },
+            ],
+        };
+        assert_eq!(verification.unrepaired(), 1);
+        assert_eq!(
+            verification.to_string(),
+            "`notes.txt`: remote object is truncated, 4 of 10 bytes are there (repaired)\n\
+             `photo.jpg`: remote object was replaced outside of Cync\n\
+             Checked 3 local files and 2 remote objects, found 2 problems"
+        );
+    }
+}
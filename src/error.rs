@@ -1,76 +1,345 @@
-use aws_sdk_s3::{operation::get_object::GetObjectError, primitives::ByteStreamError};
+use std::{fmt, io, path::PathBuf};
 use thiserror::Error;
 
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+// Error codes returned when the AWS credentials in use are missing, expired or rejected.
+const CREDENTIAL_CODES: &[&str] = &[
+    "ExpiredToken",
+    "ExpiredTokenException",
+    "InvalidToken",
+    "InvalidAccessKeyId",
+    "SignatureDoesNotMatch",
+    "UnrecognizedClientException",
+];
+
 #[derive(Error, Debug)]
 pub enum Error {
-    #[error("Missing env variable: {0}")]
-    Tui(TuiErrorKind),
+    #[error("{kind}")]
+    Tui {
+        kind: TuiErrorKind,
+        source: io::Error,
+    },
+
+    #[error("Could not read file at path: `{}`", path.display())]
+    LocalFileCorrupted { path: PathBuf, source: io::Error },
+
+    #[error("Could not read local directory at path: `{}`", path.display())]
+    LoadingLocalFiles { path: PathBuf, source: io::Error },
+
+    #[error("`{path}` only exists on the remote, there is nothing to push")]
+    NothingToPush { path: String },
+
+    #[error("`{path}` only exists locally, there is nothing to pull")]
+    NothingToPull { path: String },
+
+    #[error("Failed to write `{}` while pulling from remote", path.display())]
+    LocalSyncFailed { path: PathBuf, source: io::Error },
+
+    #[error("`{path}` does not differ between local and remote")]
+    NotAConflict { path: String },
+
+    #[error("`{path}` still contains conflict markers, the merge was not saved")]
+    UnresolvedConflict { path: String },
+
+    #[error("Failed to prepare merge file at path: `{}`", path.display())]
+    MergeFile { path: PathBuf, source: io::Error },
+
+    #[error("Failed to run editor `{editor}`")]
+    Editor { editor: String, source: io::Error },
+
+    #[error("Could not decrypt `{path}` from remote")]
+    Decryption { path: String },
+
+    #[error("`{path}` is encrypted on the remote, but no key is configured")]
+    MissingEncryptionKey { path: String },
+
+    #[error("Could not decompress `{path}` from remote")]
+    Decompression { path: String, source: io::Error },
 
-    #[error("Could not read file at path: {0}")]
-    LocalFileCorrupted(String),
+    #[error("`{path}` is truncated on the remote, {actual} of {expected} bytes are there")]
+    Truncated {
+        path: String,
+        expected: u64,
+        actual: u64,
+    },
 
-    #[error("Failed to retrieve object from host")]
-    FailedToFetchRemote,
+    #[error("`{path}` is corrupted, expected hash {expected} but got {actual}")]
+    Corrupted {
+        path: String,
+        expected: String,
+        actual: String,
+    },
 
-    #[error("Failed to upload loacal files to remote host")]
-    LoadingLocalFiles(LoadingLocalFiles),
+    #[error("Verification found {problems} problems that were not repaired")]
+    VerificationFailed { problems: usize },
 
-    #[error("Failed to sync remote with local")]
-    LocalSyncFailed,
+    #[error("No snapshot `{id}` in the bucket")]
+    SnapshotNotFound { id: String },
 
-    #[error("Failed to sync local with remote")]
-    RemoteSyncFailed,
+    #[error("Snapshot `{id}` could not be read")]
+    SnapshotCorrupted { id: String, source: BoxError },
 
-    #[error("Failed to create default Cync directory")]
-    FailedToCreateDefaultDirectory,
+    #[error("`{}` is not empty, snapshots are only restored into an empty directory", path.display())]
+    SnapshotTargetNotEmpty { path: PathBuf },
 
-    #[error("Failed to setup logging")]
-    InitializeLogging,
+    #[error(
+        "Snapshot `{id}` cannot be restored, the contents of {} file(s) are no longer stored anywhere: {}",
+        missing.len(),
+        missing.join(", ")
+    )]
+    SnapshotIncomplete { id: String, missing: Vec<String> },
+
+    #[error("Failed to delete local file at path: `{}`", path.display())]
+    LocalDeleteFailed { path: PathBuf, source: io::Error },
+
+    #[error("Failed to move `{}` to the trash", path.display())]
+    Trash { path: PathBuf, source: io::Error },
+
+    #[error("Failed to restore `{path}` from the trash")]
+    TrashRestore { path: String, source: io::Error },
+
+    #[error("Could not read the trash at path: `{}`", path.display())]
+    LoadingTrash { path: PathBuf, source: io::Error },
+
+    #[error("`{path}` is not in the trash")]
+    NotInTrash { path: String },
+
+    #[error(
+        "Failed to delete `{path}` from remote{}",
+        message.as_ref().map(|message| format!(": {message}")).unwrap_or_default()
+    )]
+    RemoteDeleteFailed {
+        path: String,
+        code: Option<String>,
+        message: Option<String>,
+    },
+
+    #[error("Failed to create default Cync directory at path: `{}`", path.display())]
+    FailedToCreateDefaultDirectory { path: PathBuf, source: io::Error },
+
+    #[error("Failed to setup logging in `{}`", path.display())]
+    InitializeLogging { path: PathBuf, source: io::Error },
 
     #[error("Failed to run setup wizard")]
-    SetupWizard(SetupWizardErrorKind),
+    SetupWizard(#[source] SetupWizardErrorKind),
+
+    #[error("Could not locate the config directory")]
+    ConfigDirectory(#[source] xdg::BaseDirectoriesError),
+
+    #[error("Config file missing at path: `{}`", path.display())]
+    ConfigFileMissing { path: PathBuf, source: io::Error },
+
+    #[error("Config file corrupted at path: `{}`", path.display())]
+    ConfigFileCorrupted { path: PathBuf, source: BoxError },
+
+    #[error(transparent)]
+    Remote(RemoteErrorKind),
+}
+
+impl Error {
+    /// Iterates over the underlying causes of this error, closest first.
+    pub fn causes(&self) -> impl Iterator<Item = &(dyn std::error::Error + 'static)> {
+        std::iter::successors(std::error::Error::source(self), |err| err.source())
+    }
+
+    /// Fatal errors leave the terminal unusable and end the session, everything else can
+    /// be shown to the user and retried.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, Error::Tui { .. })
+    }
+
+    /// A suggestion of what the user can do about this error, if there is one.
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            Error::ConfigFileMissing { .. } => Some("Run `cync init` to create a config file"),
+            Error::ConfigFileCorrupted { .. } => {
+                Some("Fix the config file by hand, or delete it and run `cync init` again")
+            }
+            Error::LoadingLocalFiles { .. } | Error::FailedToCreateDefaultDirectory { .. } => {
+                Some("Check that `local_directory_name` in the config file is a readable directory")
+            }
+            Error::LocalFileCorrupted { .. }
+            | Error::LocalSyncFailed { .. }
+            | Error::LocalDeleteFailed { .. }
+            | Error::Trash { .. }
+            | Error::TrashRestore { .. }
+            | Error::LoadingTrash { .. } => {
+                Some("Check the permissions of the file and of its parent directory")
+            }
+            Error::SnapshotNotFound { .. } => {
+                Some("Run `cync snapshot list` to see the snapshots in the bucket")
+            }
+            Error::SnapshotTargetNotEmpty { .. } => {
+                Some("Pick a new directory, or leave out --target to restore the local directory")
+            }
+            Error::SnapshotIncomplete { .. } => Some(
+                "Only files that were pushed to a versioned bucket are kept for good, run `cync versioning enable` before taking snapshots",
+            ),
+            Error::NotInTrash { .. } => Some("Run `cync trash list` to see the trashed files"),
+            Error::UnresolvedConflict { .. } => Some(
+                "Remove every <<<<<<< / ======= / >>>>>>> block in the editor, or keep one side instead",
+            ),
+            Error::Editor { .. } => Some("Set $EDITOR to the command of an installed editor"),
+            Error::Decryption { .. } => Some(
+                "Check that the passphrase or keyfile in the config file is the one the file was uploaded with",
+            ),
+            Error::Corrupted { .. } | Error::Truncated { .. } => {
+                Some("Push or pull the file again from the side that holds an intact copy")
+            }
+            Error::VerificationFailed { .. } => Some(
+                "Run `cync verify --repair` to replace damaged files from the other side, or sync the rest from the TUI",
+            ),
+            Error::MissingEncryptionKey { .. } => {
+                Some("Add an `[encryption]` section with the passphrase or keyfile to the config file")
+            }
+            Error::SetupWizard(SetupWizardErrorKind::BucketCreation { code, .. }) => {
+                match code.as_deref() {
+                    Some("BucketAlreadyExists") => Some(
+                        "Bucket names are shared by every AWS account, pick another remote directory name",
+                    ),
+                    Some("InvalidBucketName") => Some(
+                        "Bucket names must be 3-63 lowercase letters, digits, dots or hyphens",
+                    ),
+                    _ => self.credentials_hint(),
+                }
+            }
+            Error::RemoteDeleteFailed {
+                code: Some(code), ..
+            } if code == "AccessDenied" => {
+                Some("Check that your AWS account is allowed to delete objects in the bucket")
+            }
+            Error::Remote(remote) => match remote.code() {
+                Some("AccessDenied") => {
+                    Some("Check that your AWS account is allowed to access the bucket")
+                }
+                Some("NoSuchBucket") => {
+                    Some("Check `remote_directory_name` in the config file, or run `cync init`")
+                }
+                _ if remote.is_transient() => Some("Check your network connection and try again"),
+                _ => self.credentials_hint(),
+            },
+            _ => None,
+        }
+    }
+
+    fn credentials_hint(&self) -> Option<&'static str> {
+        let code = match self {
+            Error::Remote(remote) => remote.code(),
+            Error::SetupWizard(SetupWizardErrorKind::BucketCreation { code, .. }) => {
+                code.as_deref()
+            }
+            _ => None,
+        };
+        let mentions_credentials = self.causes().any(|cause| {
+            let cause = cause.to_string().to_lowercase();
+            cause.contains("credential") || cause.contains("sso")
+        });
+
+        if code.is_some_and(|code| CREDENTIAL_CODES.contains(&code)) || mentions_credentials {
+            Some("Check your AWS SSO login, e.g. run `aws sso login`")
+        } else {
+            None
+        }
+    }
+}
+
+/// Renders an error followed by its chain of causes and a hint, for printing to the user.
+pub struct Report<'a>(pub &'a Error);
+
+impl fmt::Display for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Error: {}", self.0)?;
+        for cause in self.0.causes() {
+            write!(f, "\n  Caused by: {cause}")?;
+        }
+        if let Some(hint) = self.0.hint() {
+            write!(f, "\n\nHint: {hint}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum RemoteErrorKind {
+    #[error("{operation} failed{}", code.as_ref().map(|code| format!(" ({code})")).unwrap_or_default())]
+    Permanent {
+        operation: String,
+        code: Option<String>,
+        source: BoxError,
+    },
+
+    #[error("{operation} still failing after {attempts} attempts")]
+    Transient {
+        operation: String,
+        attempts: u32,
+        source: BoxError,
+    },
+}
 
-    #[error("Config file missing")]
-    ConfigFileMissing,
+impl RemoteErrorKind {
+    pub fn code(&self) -> Option<&str> {
+        match self {
+            RemoteErrorKind::Permanent { code, .. } => code.as_deref(),
+            RemoteErrorKind::Transient { .. } => None,
+        }
+    }
 
-    #[error("Config file corrupted")]
-    ConfigFileCorrupted,
+    pub fn is_transient(&self) -> bool {
+        matches!(self, RemoteErrorKind::Transient { .. })
+    }
 }
 
 #[derive(Error, Debug)]
 pub enum SetupWizardErrorKind {
     #[error("Failed to run setup prompt")]
-    Prompt,
+    Prompt(#[source] requestty::ErrorKind),
+
+    #[error("Failed to create remote bucket `{bucket}`")]
+    BucketCreation {
+        bucket: String,
+        code: Option<String>,
+        source: BoxError,
+    },
 
-    #[error("Failed to create remote folder")]
-    BucketCreation,
+    #[error("Failed to enable versioning on bucket `{bucket}`")]
+    Versioning { bucket: String, source: BoxError },
 
-    #[error("Failed to create directory at path: `{0}`")]
-    LocalDirectoryCreation(String),
+    #[error("Failed to create directory at path: `{}`", path.display())]
+    LocalDirectoryCreation { path: PathBuf, source: io::Error },
 
     #[error("Error saving config")]
-    ConfigFile(ConfigFileErrorKind),
+    ConfigFile(#[source] ConfigFileErrorKind),
 
-    #[error("Failed to local home directory")]
+    #[error("Failed to locate home directory")]
     HomeDirectory,
+
+    #[error("Background task panicked")]
+    Task(#[source] tokio::task::JoinError),
 }
 
 #[derive(Error, Debug)]
 pub enum ConfigFileErrorKind {
-    #[error("Failed to create config file directory at path: `{0}` ")]
-    Directory(String),
+    #[error("Failed to locate the config directory")]
+    Location(#[source] xdg::BaseDirectoriesError),
 
-    #[error("Failed to create config file at path: `{0}`")]
-    FileCreation(String),
+    #[error("Failed to serialize config")]
+    Serialization(#[source] toml::ser::Error),
 
-    #[error("Failed to write to config file at path: `{0}`")]
-    FileWrite(String),
+    #[error("Failed to create config file directory at path: `{}`", path.display())]
+    Directory { path: PathBuf, source: io::Error },
+
+    #[error("Failed to create config file at path: `{}`", path.display())]
+    FileCreation { path: PathBuf, source: io::Error },
+
+    #[error("Failed to write to config file at path: `{}`", path.display())]
+    FileWrite { path: PathBuf, source: io::Error },
 }
 
 #[derive(Error, Debug)]
 pub enum TuiErrorKind {
     #[error("Failed to initialize terminal")]
-    Initilization,
+    Initialization,
 
     #[error("Error exiting application...")]
     TerminalRestoration,
@@ -82,19 +351,22 @@ pub enum TuiErrorKind {
     KeyboardEvent,
 }
 
-#[derive(Error, Debug)]
-pub enum LoadingLocalFiles {
-    #[error("Failed to upload loacal files to remote host")]
-    FileSystem,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-impl From<GetObjectError> for Error {
-    fn from(_value: GetObjectError) -> Self {
-        Error::FailedToFetchRemote
-    }
-}
-impl From<ByteStreamError> for Error {
-    fn from(_value: ByteStreamError) -> Self {
-        Error::FailedToFetchRemote
+    #[test]
+    fn test_report_renders_causes_and_hint() {
+        let err = Error::ConfigFileMissing {
+            path: PathBuf::from("/home/user/.config/.cync/config.toml"),
+            source: io::Error::new(io::ErrorKind::NotFound, "No such file or directory"),
+        };
+
+        assert_eq!(
+            Report(&err).to_string(),
+            "Error: Config file missing at path: `/home/user/.config/.cync/config.toml`\n  \
+             Caused by: No such file or directory\n\n\
+             Hint: Run `cync init` to create a config file"
+        );
     }
 }
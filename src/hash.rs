@@ -0,0 +1,213 @@
+use std::{fmt, str::FromStr};
+
+use data_encoding::{BASE64, HEXLOWER};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+// Large enough for the longest digest, SHA-256 and BLAKE3 at 32 bytes.
+const MAX_DIGEST_LEN: usize = 32;
+
+/// How file contents are hashed to tell whether the two sides match.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HashAlgorithm {
+    Md5,
+    #[default]
+    Sha256,
+    Blake3,
+    Crc32c,
+}
+
+impl HashAlgorithm {
+    const ALL: [HashAlgorithm; 4] = [
+        HashAlgorithm::Md5,
+        HashAlgorithm::Sha256,
+        HashAlgorithm::Blake3,
+        HashAlgorithm::Crc32c,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Md5 => "md5",
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Blake3 => "blake3",
+            HashAlgorithm::Crc32c => "crc32c",
+        }
+    }
+
+    pub fn compute(self, contents: &[u8]) -> FileHash {
+        match self {
+            HashAlgorithm::Md5 => FileHash::new(self, &md5::compute(contents).0),
+            HashAlgorithm::Sha256 => FileHash::new(self, &Sha256::digest(contents)),
+            HashAlgorithm::Blake3 => FileHash::new(self, blake3::hash(contents).as_bytes()),
+            HashAlgorithm::Crc32c => FileHash::new(self, &crc32c::crc32c(contents).to_be_bytes()),
+        }
+    }
+
+    fn digest_len(self) -> usize {
+        match self {
+            HashAlgorithm::Md5 => 16,
+            HashAlgorithm::Sha256 | HashAlgorithm::Blake3 => 32,
+            HashAlgorithm::Crc32c => 4,
+        }
+    }
+}
+
+/// A digest of file contents, along with the algorithm that produced it.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileHash {
+    algorithm: HashAlgorithm,
+    digest: [u8; MAX_DIGEST_LEN],
+}
+
+impl FileHash {
+    fn new(algorithm: HashAlgorithm, bytes: &[u8]) -> Self {
+        let mut digest = [0; MAX_DIGEST_LEN];
+        digest[..bytes.len()].copy_from_slice(bytes);
+        FileHash { algorithm, digest }
+    }
+
+    /// Builds a hash from its raw bytes, if they have the algorithm's length.
+    pub fn from_bytes(algorithm: HashAlgorithm, bytes: &[u8]) -> Option<Self> {
+        (bytes.len() == algorithm.digest_len()).then(|| FileHash::new(algorithm, bytes))
+    }
+
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.digest[..self.algorithm.digest_len()]
+    }
+
+    /// Whether `contents` hash to this, using the same algorithm.
+    pub fn matches(&self, contents: &[u8]) -> bool {
+        self.algorithm.compute(contents) == *self
+    }
+
+    /// The digest in hex, prefixed with the algorithm, e.g. `sha256:9f86…`. This is the form
+    /// stored in object metadata, the hash cache and snapshot manifests, read back by `FromStr`.
+    pub fn to_tagged(self) -> String {
+        format!("{}:{self}", self.algorithm.name())
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("Invalid hash `{0}`, expected `<algorithm>:<hex>`")]
+pub struct InvalidHash(String);
+
+/// Parses the form written by `FileHash::to_tagged`.
+impl FromStr for FileHash {
+    type Err = InvalidHash;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidHash(text.to_string());
+        let (name, hex) = text.split_once(':').ok_or_else(invalid)?;
+        let algorithm = HashAlgorithm::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.name() == name)
+            .ok_or_else(invalid)?;
+        let bytes = HEXLOWER.decode(hex.as_bytes()).map_err(|_| invalid())?;
+        FileHash::from_bytes(algorithm, &bytes).ok_or_else(invalid)
+    }
+}
+
+/// The digest in hex.
+impl fmt::Display for FileHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&HEXLOWER.encode(self.as_bytes()))
+    }
+}
+
+impl fmt::Debug for FileHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_tagged())
+    }
+}
+
+/// A checksum S3 verifies an upload against, so bytes damaged on the way are rejected.
+#[derive(Clone, Debug, PartialEq)]
+pub enum S3Checksum {
+    Sha256(String),
+    Crc32c(String),
+}
+
+impl S3Checksum {
+    /// SHA-256 when that is the configured algorithm, and the cheaper CRC32C otherwise, since
+    /// S3 supports neither MD5 nor BLAKE3 as additional checksums.
+    pub fn new(algorithm: HashAlgorithm, object: &[u8]) -> Self {
+        let algorithm = match algorithm {
+            HashAlgorithm::Sha256 => HashAlgorithm::Sha256,
+            _ => HashAlgorithm::Crc32c,
+        };
+        let encoded = BASE64.encode(algorithm.compute(object).as_bytes());
+        match algorithm {
+            HashAlgorithm::Sha256 => S3Checksum::Sha256(encoded),
+            _ => S3Checksum::Crc32c(encoded),
+        }
+    }
+
+    /// The checksum S3 returned for an object, preferring SHA-256 when it has both.
+    pub fn from_response(sha256: Option<&str>, crc32c: Option<&str>) -> Option<Self> {
+        sha256
+            .map(|checksum| S3Checksum::Sha256(checksum.to_string()))
+            .or_else(|| crc32c.map(|checksum| S3Checksum::Crc32c(checksum.to_string())))
+    }
+
+    /// The checksum S3 reports for a whole object, as a hash. Checksums of multipart uploads
+    /// are checksums of the parts, marked with a `-<parts>` suffix, and are left out.
+    pub fn to_hash(&self) -> Option<FileHash> {
+        let (algorithm, encoded) = match self {
+            S3Checksum::Sha256(encoded) => (HashAlgorithm::Sha256, encoded),
+            S3Checksum::Crc32c(encoded) => (HashAlgorithm::Crc32c, encoded),
+        };
+        let bytes = BASE64.decode(encoded.as_bytes()).ok()?;
+        FileHash::from_bytes(algorithm, &bytes)
+    }
+}
+
+/// The checksum as a tagged hash, or as S3 sent it for multipart uploads.
+impl fmt::Display for S3Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(hash) = self.to_hash() {
+            return f.write_str(&hash.to_tagged());
+        }
+        match self {
+            S3Checksum::Sha256(encoded) => write!(f, "sha256 of parts {encoded}"),
+            S3Checksum::Crc32c(encoded) => write!(f, "crc32c of parts {encoded}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hashes_round_trip_through_text() {
+        for algorithm in HashAlgorithm::ALL {
+            let hash = algorithm.compute(b"hello");
+            assert_eq!(hash.as_bytes().len(), algorithm.digest_len());
+            assert_eq!(hash.to_tagged().parse::<FileHash>().ok(), Some(hash));
+            assert!(hash.matches(b"hello"));
+            assert!(!hash.matches(b"hello!"));
+        }
+        assert_eq!(
+            HashAlgorithm::Crc32c.compute(b"123456789").to_string(),
+            "e3069283"
+        );
+
+        assert!("sha256:abc".parse::<FileHash>().is_err());
+
+        let checksum = S3Checksum::new(HashAlgorithm::Sha256, b"hello");
+        assert_eq!(
+            checksum.to_hash(),
+            Some(HashAlgorithm::Sha256.compute(b"hello"))
+        );
+        assert_eq!(
+            checksum.to_string(),
+            HashAlgorithm::Sha256.compute(b"hello").to_tagged()
+        );
+    }
+}
@@ -26,9 +26,15 @@ pub fn get_data_dir() -> PathBuf {
 
 pub fn initialize_logging() -> Result<(), Error> {
     let directory = get_data_dir();
-    std::fs::create_dir_all(directory.clone()).map_err(|_| Error::InitializeLogging)?;
+    std::fs::create_dir_all(directory.clone()).map_err(|source| Error::InitializeLogging {
+        path: directory.clone(),
+        source,
+    })?;
     let log_path = directory.join(LOG_FILE.clone());
-    let log_file = std::fs::File::create(log_path).map_err(|_| Error::InitializeLogging)?;
+    let log_file = std::fs::File::create(&log_path).map_err(|source| Error::InitializeLogging {
+        path: log_path.clone(),
+        source,
+    })?;
     std::env::set_var(
         "RUST_LOG",
         std::env::var("RUST_LOG")
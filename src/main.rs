@@ -1,45 +1,200 @@
-use crate::error::Error;
-use clap::Parser;
-use cync::Cync;
+use crate::error::{Error, Report};
+use aws_sdk_s3::types::BucketVersioningStatus;
+use clap::{Parser, Subcommand, ValueEnum};
+use cync::{config::Config, snapshot, trash::Trash, verify::verify, Cync};
 use logging::initialize_logging;
 use setup::run_setup_wizard;
+use std::{path::PathBuf, process::ExitCode};
 use tui::run_tui;
-use util::{initialize_terminal, restore_terminal};
+use util::{
+    format_size, handle_termination_signals, initialize_panic_handler, initialize_terminal,
+    restore_terminal,
+};
 
+mod compression;
+mod crypto;
 mod cync;
 mod error;
+mod hash;
 mod logging;
+mod retry;
 mod s3;
 mod setup;
+mod sse;
+mod throttle;
 mod tui;
 mod util;
 
 #[derive(Parser)]
 struct Args {
-    init: Option<String>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Set up the config file and the bucket
+    Init,
+    /// Check that local files and remote objects still match the hashes recorded for them
+    Verify {
+        /// Only read object metadata instead of downloading every object
+        #[arg(long)]
+        quick: bool,
+        /// Replace damaged files from the side that still holds the last synced version
+        #[arg(long)]
+        repair: bool,
+    },
+    /// Show whether the bucket keeps earlier versions of files, or change it
+    Versioning {
+        #[arg(value_enum)]
+        change: Option<VersioningChange>,
+    },
+    /// Manage the local files that were overwritten or deleted
+    #[command(subcommand)]
+    Trash(TrashCommand),
+    /// Record or bring back the state of every file at one moment
+    #[command(subcommand)]
+    Snapshot(SnapshotCommand),
+}
+
+#[derive(Subcommand)]
+enum TrashCommand {
+    /// List the trashed files, newest first
+    List,
+    /// Put the most recently trashed copy of a file back in the local directory
+    Restore { path: String },
+    /// Delete every trashed file for good
+    Empty,
+}
+
+#[derive(Subcommand)]
+enum SnapshotCommand {
+    /// Store a manifest of every file and its remote version in the bucket
+    Create {
+        /// A note to tell the snapshot apart by
+        #[arg(long)]
+        label: Option<String>,
+    },
+    /// List the snapshots in the bucket, oldest first
+    List,
+    /// Bring the local directory back to the state of a snapshot
+    Restore {
+        id: String,
+        /// Restore into this empty directory instead of the local directory
+        #[arg(long)]
+        target: Option<PathBuf>,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum VersioningChange {
+    Enable,
+    /// Stop keeping new versions, the ones kept so far stay
+    Suspend,
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Error> {
+async fn main() -> ExitCode {
+    match run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            tracing::error!("{}", Report(&err));
+            eprintln!("{}", Report(&err));
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run() -> Result<(), Error> {
     initialize_logging()?;
+    initialize_panic_handler();
     let aws_config = &aws_config::load_from_env().await;
 
-    let Args { init } = Args::parse();
-
-    let res = if init.is_some() {
-        run_setup_wizard().await
-    } else {
-        let mut terminal = initialize_terminal()?;
-        let mut app = Cync::new(aws_config).await?;
-        let app_res = run_tui(&mut terminal, &mut app).await;
-        // TODO: Restoration seems to be broken if app panics?
-        restore_terminal(terminal)?;
-        app_res
-    };
-
-    if let Err(err) = res {
-        println!("{err:?}");
-    }
+    let Args { command } = Args::parse();
 
-    Ok(())
+    match command {
+        Some(Command::Init) => run_setup_wizard().await,
+        Some(Command::Verify { quick, repair }) => {
+            let config = Config::load(aws_config)?;
+            let verification = verify(&config, quick, repair).await?;
+            println!("{verification}");
+            match verification.unrepaired() {
+                0 => Ok(()),
+                problems => Err(Error::VerificationFailed { problems }),
+            }
+        }
+        Some(Command::Versioning { change }) => {
+            let config = Config::load(aws_config)?;
+            let client = config.aws_client();
+            let bucket = config.remote_directory().to_string();
+            if let Some(change) = change {
+                client
+                    .set_versioning(bucket.clone(), change == VersioningChange::Enable)
+                    .await?;
+            }
+            let status = match client.versioning(bucket).await? {
+                Some(BucketVersioningStatus::Enabled) => "enabled",
+                Some(BucketVersioningStatus::Suspended) => "suspended",
+                _ => "off",
+            };
+            println!("Versioning of `{}` is {status}", config.remote_directory());
+            Ok(())
+        }
+        Some(Command::Trash(command)) => {
+            let config = Config::load(aws_config)?;
+            let trash = Trash::new(&config);
+            match command {
+                TrashCommand::List => {
+                    for entry in trash.entries()? {
+                        println!(
+                            "{}  {:>10}  {}",
+                            entry.trashed_at.format("%Y-%m-%d %H:%M:%S"),
+                            format_size(entry.size),
+                            entry.path
+                        );
+                    }
+                }
+                TrashCommand::Restore { path } => {
+                    let entry = trash
+                        .entries()?
+                        .into_iter()
+                        .find(|entry| entry.path == path)
+                        .ok_or(Error::NotInTrash { path })?;
+                    trash.restore(&entry)?;
+                    println!("Restored `{}`", entry.path);
+                }
+                TrashCommand::Empty => println!("Deleted {} file(s)", trash.empty()?),
+            }
+            Ok(())
+        }
+        Some(Command::Snapshot(command)) => {
+            let config = Config::load(aws_config)?;
+            match command {
+                SnapshotCommand::Create { label } => {
+                    let snapshot = snapshot::create(&config, label).await?;
+                    println!("Created snapshot {snapshot}");
+                }
+                SnapshotCommand::List => {
+                    for snapshot in snapshot::list(&config).await? {
+                        println!("{snapshot}");
+                    }
+                }
+                SnapshotCommand::Restore { id, target } => {
+                    let restoration = snapshot::restore(&config, &id, target).await?;
+                    println!("{restoration}");
+                }
+            }
+            Ok(())
+        }
+        None => {
+            let mut terminal = initialize_terminal()?;
+            handle_termination_signals();
+            let app_res = match Cync::new(aws_config).await {
+                Ok(mut app) => run_tui(&mut terminal, &mut app).await,
+                Err(err) => Err(err),
+            };
+            restore_terminal(terminal)?;
+            app_res
+        }
+    }
 }
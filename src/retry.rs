@@ -0,0 +1,197 @@
+use std::{future::Future, time::Duration};
+
+use aws_sdk_s3::{
+    error::{ProvideErrorMetadata, SdkError},
+    primitives::ByteStreamError,
+};
+use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+use rand::Rng;
+use tracing::warn;
+
+use crate::error::{Error, RemoteErrorKind};
+
+// Error codes S3 uses for conditions that clear up on their own.
+const TRANSIENT_CODES: &[&str] = &[
+    "SlowDown",
+    "Throttling",
+    "ThrottlingException",
+    "RequestTimeout",
+    "RequestTimeTooSkewed",
+    "InternalError",
+    "ServiceUnavailable",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorClass {
+    Transient,
+    Permanent,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Jitter {
+    None,
+    #[default]
+    Full,
+    Equal,
+}
+
+/// Contents of the `[retry]` section of the config file.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct RetryConfig {
+    pub max_attempts: u32,
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+    pub jitter: Jitter,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_attempts: 5,
+            base_delay_ms: 200,
+            max_delay_ms: 20_000,
+            jitter: Jitter::Full,
+        }
+    }
+}
+
+/// A failed attempt at a remote operation, tagged with whether trying again may help.
+pub struct Failure {
+    pub class: ErrorClass,
+    pub code: Option<String>,
+    pub source: Box<dyn std::error::Error + Send + Sync>,
+}
+
+impl<E> From<SdkError<E, HttpResponse>> for Failure
+where
+    E: ProvideErrorMetadata + std::error::Error + Send + Sync + 'static,
+{
+    fn from(err: SdkError<E, HttpResponse>) -> Self {
+        let code = err.code().map(str::to_string);
+        Failure {
+            class: classify(&err),
+            code,
+            source: Box::new(err),
+        }
+    }
+}
+
+impl From<ByteStreamError> for Failure {
+    fn from(err: ByteStreamError) -> Self {
+        // The connection dropped while the body was streaming, the request itself was fine.
+        Failure {
+            class: ErrorClass::Transient,
+            code: None,
+            source: Box::new(err),
+        }
+    }
+}
+
+pub fn classify<E: ProvideErrorMetadata>(err: &SdkError<E, HttpResponse>) -> ErrorClass {
+    match err {
+        SdkError::TimeoutError(_) | SdkError::ResponseError(_) => ErrorClass::Transient,
+        SdkError::DispatchFailure(failure) if failure.is_user() => ErrorClass::Permanent,
+        SdkError::DispatchFailure(_) => ErrorClass::Transient,
+        SdkError::ServiceError(service) => {
+            let status = service.raw().status().as_u16();
+            if status == 429
+                || status >= 500
+                || err
+                    .code()
+                    .is_some_and(|code| TRANSIENT_CODES.contains(&code))
+            {
+                ErrorClass::Transient
+            } else {
+                ErrorClass::Permanent
+            }
+        }
+        _ => ErrorClass::Permanent,
+    }
+}
+
+#[derive(Clone)]
+pub struct RetryPolicy {
+    config: RetryConfig,
+}
+
+impl RetryPolicy {
+    pub fn new(config: RetryConfig) -> Self {
+        Self { config }
+    }
+
+    /// Delay before retry number `attempt` (starting at 1): exponential growth capped at
+    /// `max_delay_ms`, then spread out according to the configured jitter.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self
+            .config
+            .base_delay_ms
+            .saturating_mul(1 << attempt.saturating_sub(1).min(32))
+            .min(self.config.max_delay_ms);
+        let millis = match self.config.jitter {
+            Jitter::None => exponential,
+            Jitter::Full => rand::thread_rng().gen_range(0..=exponential),
+            Jitter::Equal => exponential / 2 + rand::thread_rng().gen_range(0..=exponential / 2),
+        };
+        Duration::from_millis(millis)
+    }
+
+    /// Runs `attempt` until it succeeds, fails permanently or runs out of attempts.
+    /// `operation` describes the request in the resulting error.
+    pub async fn run<T, F, Fut>(&self, operation: String, mut attempt: F) -> Result<T, Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, Failure>>,
+    {
+        let max_attempts = self.config.max_attempts.max(1);
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let failure = match attempt().await {
+                Ok(value) => return Ok(value),
+                Err(failure) => failure,
+            };
+
+            if failure.class == ErrorClass::Permanent {
+                return Err(Error::Remote(RemoteErrorKind::Permanent {
+                    operation,
+                    code: failure.code,
+                    source: failure.source,
+                }));
+            }
+            if attempts >= max_attempts {
+                return Err(Error::Remote(RemoteErrorKind::Transient {
+                    operation,
+                    attempts,
+                    source: failure.source,
+                }));
+            }
+
+            let delay = self.backoff(attempts);
+            warn!(
+                "{operation} failed (attempt {attempts}/{max_attempts}), retrying in {delay:?}: {}",
+                failure.source
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_grows_exponentially_up_to_the_cap() {
+        let policy = RetryPolicy::new(RetryConfig {
+            max_attempts: 10,
+            base_delay_ms: 100,
+            max_delay_ms: 1_000,
+            jitter: Jitter::None,
+        });
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(3), Duration::from_millis(400));
+        assert_eq!(policy.backoff(8), Duration::from_millis(1_000));
+    }
+}
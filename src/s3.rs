@@ -1,62 +1,371 @@
 use aws_sdk_s3 as s3;
-use aws_smithy_async::future::pagination_stream::PaginationStream;
-use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+use bytes::Bytes;
 use s3::{
-    error::SdkError,
     operation::{
-        get_object::{GetObjectError, GetObjectOutput},
-        list_objects_v2::{ListObjectsV2Error, ListObjectsV2Output},
-        put_object::{PutObjectError, PutObjectOutput},
+        head_object::HeadObjectOutput, list_object_versions::ListObjectVersionsOutput,
+        list_objects_v2::ListObjectsV2Output, put_object::PutObjectOutput,
     },
+    primitives::ByteStream,
+    types::{
+        BucketVersioningStatus, ChecksumMode, Delete, ObjectIdentifier, VersioningConfiguration,
+    },
+};
+use std::{collections::HashMap, ops::Range};
+
+use crate::{
+    error::Error,
+    hash::S3Checksum,
+    retry::{Failure, RetryPolicy},
+    sse::ServerSideEncryption,
+    throttle::Throttle,
 };
 
+// Maximum number of keys a single DeleteObjects request accepts.
+const DELETE_BATCH_SIZE: usize = 1000;
+
+/// A key that S3 refused to delete as part of a batch.
+pub struct DeleteFailure {
+    pub key: String,
+    pub code: Option<String>,
+    pub message: Option<String>,
+}
+
+/// An object's contents and user metadata, along with the checksum S3 stored for it.
+pub struct RemoteObject {
+    pub contents: Vec<u8>,
+    pub metadata: HashMap<String, String>,
+    pub checksum: Option<S3Checksum>,
+}
+
 pub struct S3Client {
     inner: s3::Client,
+    throttle: Throttle,
+    retry: RetryPolicy,
+    sse: Option<ServerSideEncryption>,
 }
 
 impl S3Client {
-    pub fn new(inner: s3::Client) -> Self {
-        Self { inner }
+    pub fn new(
+        inner: s3::Client,
+        throttle: Throttle,
+        retry: RetryPolicy,
+        sse: Option<ServerSideEncryption>,
+    ) -> Self {
+        Self {
+            inner,
+            throttle,
+            retry,
+            sse,
+        }
     }
 
+    pub fn throttle(&self) -> &Throttle {
+        &self.throttle
+    }
+
+    /// Uploads `contents`, which S3 rejects unless they match `checksum` on arrival.
     pub async fn put_object<T: Into<String> + 'static>(
         &self,
         bucket_name: T,
         object_name: T,
-        body: s3::primitives::ByteStream,
-    ) -> Result<PutObjectOutput, SdkError<PutObjectError>> {
-        self.inner
-            .put_object()
-            .bucket(bucket_name)
-            .key(object_name)
-            .body(body)
-            .send()
+        contents: Vec<u8>,
+        metadata: HashMap<String, String>,
+        checksum: S3Checksum,
+    ) -> Result<PutObjectOutput, Error> {
+        let (bucket_name, object_name) = (bucket_name.into(), object_name.into());
+        let contents = Bytes::from(contents);
+        self.retry
+            .run(format!("Uploading `{object_name}`"), || async {
+                let request = self
+                    .inner
+                    .put_object()
+                    .bucket(&bucket_name)
+                    .key(&object_name)
+                    .set_metadata(Some(metadata.clone()))
+                    .body(
+                        self.throttle
+                            .upload_body(ByteStream::from(contents.clone())),
+                    );
+                let request = match &checksum {
+                    S3Checksum::Sha256(checksum) => request.checksum_sha256(checksum),
+                    S3Checksum::Crc32c(checksum) => request.checksum_crc32_c(checksum),
+                };
+                let request = match &self.sse {
+                    Some(sse) => sse.put(request),
+                    None => request,
+                };
+                Ok(request.send().await?)
+            })
             .await
     }
 
+    /// Fetches a single page of the bucket listing, starting after `continuation_token`.
     pub async fn list_objects<T: Into<String> + 'static>(
         &self,
         bucket_name: T,
-    ) -> PaginationStream<Result<ListObjectsV2Output, SdkError<ListObjectsV2Error, HttpResponse>>>
-    {
-        self.inner
-            .list_objects_v2()
-            .bucket(bucket_name)
-            .max_keys(10)
-            .into_paginator()
-            .send()
+        continuation_token: Option<String>,
+    ) -> Result<ListObjectsV2Output, Error> {
+        let bucket_name = bucket_name.into();
+        self.retry
+            .run(format!("Listing bucket `{bucket_name}`"), || async {
+                Ok(self
+                    .inner
+                    .list_objects_v2()
+                    .bucket(&bucket_name)
+                    .max_keys(10)
+                    .set_continuation_token(continuation_token.clone())
+                    .send()
+                    .await?)
+            })
+            .await
+    }
+
+    /// Downloads an object's contents and user metadata. The request is retried as a whole if
+    /// the body stream breaks part way through.
+    pub async fn download_object<T: Into<String> + 'static>(
+        &self,
+        bucket_name: T,
+        file_path: T,
+    ) -> Result<RemoteObject, Error> {
+        self.download(bucket_name.into(), file_path.into(), None)
+            .await
     }
 
-    pub async fn get_object<T: Into<String> + 'static>(
+    /// Downloads an earlier version of an object, as `download_object` does the current one.
+    pub async fn download_version<T: Into<String> + 'static>(
         &self,
         bucket_name: T,
         file_path: T,
-    ) -> Result<GetObjectOutput, SdkError<GetObjectError, HttpResponse>> {
-        self.inner
-            .get_object()
-            .bucket(bucket_name)
-            .key(file_path)
-            .send()
+        version_id: T,
+    ) -> Result<RemoteObject, Error> {
+        self.download(
+            bucket_name.into(),
+            file_path.into(),
+            Some(version_id.into()),
+        )
+        .await
+    }
+
+    async fn download(
+        &self,
+        bucket_name: String,
+        file_path: String,
+        version_id: Option<String>,
+    ) -> Result<RemoteObject, Error> {
+        self.retry
+            .run(format!("Downloading `{file_path}`"), || async {
+                let request = self
+                    .inner
+                    .get_object()
+                    .bucket(&bucket_name)
+                    .key(&file_path)
+                    .set_version_id(version_id.clone())
+                    .checksum_mode(ChecksumMode::Enabled);
+                let request = match &self.sse {
+                    Some(sse) => sse.get(request),
+                    None => request,
+                };
+                let object = request.send().await?;
+                let metadata = object.metadata.clone().unwrap_or_default();
+                let checksum =
+                    S3Checksum::from_response(object.checksum_sha256(), object.checksum_crc32_c());
+                let contents = self.throttle.download_body(object.body).await?;
+                Ok::<_, Failure>(RemoteObject {
+                    contents,
+                    metadata,
+                    checksum,
+                })
+            })
             .await
     }
+
+    pub async fn head_object<T: Into<String> + 'static>(
+        &self,
+        bucket_name: T,
+        file_path: T,
+    ) -> Result<HeadObjectOutput, Error> {
+        let (bucket_name, file_path) = (bucket_name.into(), file_path.into());
+        self.retry
+            .run(format!("Reading metadata of `{file_path}`"), || async {
+                let request = self
+                    .inner
+                    .head_object()
+                    .bucket(&bucket_name)
+                    .key(&file_path)
+                    .checksum_mode(ChecksumMode::Enabled);
+                let request = match &self.sse {
+                    Some(sse) => sse.head(request),
+                    None => request,
+                };
+                Ok(request.send().await?)
+            })
+            .await
+    }
+
+    /// Downloads the bytes of an object in `range`, which must not be empty.
+    pub async fn download_range<T: Into<String> + 'static>(
+        &self,
+        bucket_name: T,
+        file_path: T,
+        range: Range<u64>,
+    ) -> Result<Vec<u8>, Error> {
+        let (bucket_name, file_path) = (bucket_name.into(), file_path.into());
+        let header = format!("bytes={}-{}", range.start, range.end - 1);
+        self.retry
+            .run(format!("Downloading part of `{file_path}`"), || async {
+                let request = self
+                    .inner
+                    .get_object()
+                    .bucket(&bucket_name)
+                    .key(&file_path)
+                    .range(&header);
+                let request = match &self.sse {
+                    Some(sse) => sse.get(request),
+                    None => request,
+                };
+                let object = request.send().await?;
+                Ok::<_, Failure>(self.throttle.download_body(object.body).await?)
+            })
+            .await
+    }
+
+    /// Fetches a single page of the versions and delete markers of the keys under `prefix`,
+    /// starting after the given markers.
+    pub async fn list_object_versions<T: Into<String> + 'static>(
+        &self,
+        bucket_name: T,
+        prefix: Option<String>,
+        markers: Option<(String, String)>,
+    ) -> Result<ListObjectVersionsOutput, Error> {
+        let bucket_name = bucket_name.into();
+        let (key_marker, version_id_marker) = markers.unzip();
+        self.retry
+            .run(format!("Listing versions in `{bucket_name}`"), || async {
+                Ok(self
+                    .inner
+                    .list_object_versions()
+                    .bucket(&bucket_name)
+                    .set_prefix(prefix.clone())
+                    .set_key_marker(key_marker.clone())
+                    .set_version_id_marker(version_id_marker.clone())
+                    .send()
+                    .await?)
+            })
+            .await
+    }
+
+    /// Permanently deletes one version of an object. Deleting a delete marker brings back the
+    /// version before it.
+    pub async fn delete_version<T: Into<String> + 'static>(
+        &self,
+        bucket_name: T,
+        file_path: T,
+        version_id: T,
+    ) -> Result<(), Error> {
+        let (bucket_name, file_path, version_id) =
+            (bucket_name.into(), file_path.into(), version_id.into());
+        self.retry
+            .run(format!("Deleting a version of `{file_path}`"), || async {
+                self.inner
+                    .delete_object()
+                    .bucket(&bucket_name)
+                    .key(&file_path)
+                    .version_id(&version_id)
+                    .send()
+                    .await?;
+                Ok(())
+            })
+            .await
+    }
+
+    /// Whether the bucket keeps earlier versions of objects, `None` if it never has.
+    pub async fn versioning<T: Into<String> + 'static>(
+        &self,
+        bucket_name: T,
+    ) -> Result<Option<BucketVersioningStatus>, Error> {
+        let bucket_name = bucket_name.into();
+        self.retry
+            .run(format!("Reading versioning of `{bucket_name}`"), || async {
+                let output = self
+                    .inner
+                    .get_bucket_versioning()
+                    .bucket(&bucket_name)
+                    .send()
+                    .await?;
+                Ok(output.status().cloned())
+            })
+            .await
+    }
+
+    /// Turns versioning on, or suspends it. Versions kept so far stay either way.
+    pub async fn set_versioning<T: Into<String> + 'static>(
+        &self,
+        bucket_name: T,
+        enabled: bool,
+    ) -> Result<(), Error> {
+        let bucket_name = bucket_name.into();
+        let status = if enabled {
+            BucketVersioningStatus::Enabled
+        } else {
+            BucketVersioningStatus::Suspended
+        };
+        let configuration = VersioningConfiguration::builder().status(status).build();
+        self.retry
+            .run(
+                format!("Changing versioning of `{bucket_name}`"),
+                || async {
+                    self.inner
+                        .put_bucket_versioning()
+                        .bucket(&bucket_name)
+                        .versioning_configuration(configuration.clone())
+                        .send()
+                        .await?;
+                    Ok(())
+                },
+            )
+            .await
+    }
+
+    /// Deletes `keys` with as few requests as possible. Keys that could not be deleted are
+    /// returned rather than failing the whole batch.
+    pub async fn delete_objects<T: Into<String> + 'static>(
+        &self,
+        bucket_name: T,
+        keys: Vec<String>,
+    ) -> Result<Vec<DeleteFailure>, Error> {
+        let bucket_name = bucket_name.into();
+        let mut failures = Vec::new();
+        for batch in keys.chunks(DELETE_BATCH_SIZE) {
+            let objects = batch
+                .iter()
+                .map(|key| ObjectIdentifier::builder().key(key).build())
+                .collect::<Result<Vec<_>, _>>()
+                .expect("object identifiers always have a key");
+            let delete = Delete::builder()
+                .set_objects(Some(objects))
+                .quiet(true)
+                .build()
+                .expect("delete requests always have objects");
+
+            let output = self
+                .retry
+                .run(format!("Deleting {} object(s)", batch.len()), || async {
+                    Ok(self
+                        .inner
+                        .delete_objects()
+                        .bucket(&bucket_name)
+                        .delete(delete.clone())
+                        .send()
+                        .await?)
+                })
+                .await?;
+
+            failures.extend(output.errors().iter().map(|error| DeleteFailure {
+                key: error.key().unwrap_or_default().to_string(),
+                code: error.code().map(str::to_string),
+                message: error.message().map(str::to_string),
+            }));
+        }
+        Ok(failures)
+    }
 }
@@ -2,6 +2,10 @@ use crate::{
     cync::config::ConfigFile,
     error::{ConfigFileErrorKind, Error, SetupWizardErrorKind},
 };
+use aws_sdk_s3::{
+    error::ProvideErrorMetadata,
+    types::{BucketVersioningStatus, VersioningConfiguration},
+};
 use requestty::Question;
 use std::{collections::HashMap, fs, io::Write, sync::Arc};
 
@@ -24,7 +28,7 @@ pub async fn run_setup_wizard() -> Result<(), Error> {
 
     let answers = Arc::new(
         requestty::prompt(questions)
-            .map_err(|_| Error::SetupWizard(SetupWizardErrorKind::Prompt))?
+            .map_err(|source| Error::SetupWizard(SetupWizardErrorKind::Prompt(source)))?
             .into_iter()
             .fold(HashMap::new(), |mut acc, (question, answer)| {
                 let ans = match answer {
@@ -36,6 +40,16 @@ pub async fn run_setup_wizard() -> Result<(), Error> {
             }),
     );
 
+    let versioning = requestty::prompt_one(
+        Question::confirm("versioning")
+            .message("Keep earlier versions of files in the bucket, so overwritten and deleted files can be restored?")
+            .default(true)
+            .build(),
+    )
+    .map_err(|source| Error::SetupWizard(SetupWizardErrorKind::Prompt(source)))?
+    .as_bool()
+    .unwrap_or_default();
+
     let local_directory_name = answers
         .get(&String::from("local_directory"))
         .expect("user must provide local directory name");
@@ -55,54 +69,88 @@ pub async fn run_setup_wizard() -> Result<(), Error> {
             .bucket(remote_directory_name)
             .send()
             .await
-            .map(|_| remote_directory_name.clone())
-            // TODO: Investigate AWS error types to be more explicit as to why operation failed
-            // Two most likely erorrs are BucketAlreadyExists and invalid bucket names
-            .map_err(|_| Error::SetupWizard(SetupWizardErrorKind::BucketCreation))
+            .map_err(|source| {
+                Error::SetupWizard(SetupWizardErrorKind::BucketCreation {
+                    bucket: remote_directory_name.clone(),
+                    code: source.code().map(str::to_string),
+                    source: source.into(),
+                })
+            })?;
+
+        if versioning {
+            let configuration = VersioningConfiguration::builder()
+                .status(BucketVersioningStatus::Enabled)
+                .build();
+            aws_client
+                .put_bucket_versioning()
+                .bucket(remote_directory_name)
+                .versioning_configuration(configuration)
+                .send()
+                .await
+                .map_err(|source| {
+                    Error::SetupWizard(SetupWizardErrorKind::Versioning {
+                        bucket: remote_directory_name.clone(),
+                        source: source.into(),
+                    })
+                })?;
+        }
+        Ok(remote_directory_name.clone())
     });
 
     let home_dir =
         home::home_dir().ok_or(Error::SetupWizard(SetupWizardErrorKind::HomeDirectory))?;
-    let full_local_directory_path =
-        format!("{}/{}", home_dir.display(), local_directory_name.clone());
-    fs::create_dir(full_local_directory_path.clone()).map_err(|_| {
-        Error::SetupWizard(SetupWizardErrorKind::LocalDirectoryCreation(
-            full_local_directory_path.clone(),
-        ))
+    let full_local_directory_path = home_dir.join(local_directory_name);
+    fs::create_dir(&full_local_directory_path).map_err(|source| {
+        Error::SetupWizard(SetupWizardErrorKind::LocalDirectoryCreation {
+            path: full_local_directory_path.clone(),
+            source,
+        })
     })?;
 
     let remote_directory_name = remote_handle
         .await
-        .map_err(|_| Error::SetupWizard(SetupWizardErrorKind::BucketCreation))??;
+        .map_err(|source| Error::SetupWizard(SetupWizardErrorKind::Task(source)))??;
 
-    let xdg_config = xdg::BaseDirectories::new().unwrap().get_config_home();
-    let full_config_path = format!("{}.cync", xdg_config.display());
+    let xdg_config = xdg::BaseDirectories::new()
+        .map_err(|source| config_file_error(ConfigFileErrorKind::Location(source)))?
+        .get_config_home();
+    let full_config_path = xdg_config.join(".cync");
 
     let config_file = ConfigFile {
         remote_directory_name,
-        local_directory_name: full_local_directory_path.into(),
+        local_directory_name: full_local_directory_path,
+        ..Default::default()
     };
 
-    let toml = toml::to_string(&config_file).unwrap();
+    let toml = toml::to_string(&config_file)
+        .map_err(|source| config_file_error(ConfigFileErrorKind::Serialization(source)))?;
 
-    fs::create_dir(full_config_path.clone()).map_err(|_| {
-        Error::SetupWizard(SetupWizardErrorKind::ConfigFile(
-            ConfigFileErrorKind::Directory(full_config_path.clone()),
-        ))
+    fs::create_dir(&full_config_path).map_err(|source| {
+        config_file_error(ConfigFileErrorKind::Directory {
+            path: full_config_path.clone(),
+            source,
+        })
     })?;
 
-    fs::File::create_new(format!("{}/config.toml", full_config_path))
-        .map_err(|_| {
-            Error::SetupWizard(SetupWizardErrorKind::ConfigFile(
-                ConfigFileErrorKind::FileCreation(full_config_path.clone()),
-            ))
+    let config_file_path = full_config_path.join("config.toml");
+    fs::File::create_new(&config_file_path)
+        .map_err(|source| {
+            config_file_error(ConfigFileErrorKind::FileCreation {
+                path: config_file_path.clone(),
+                source,
+            })
         })?
         .write_all(toml.as_bytes())
-        .map_err(|_| {
-            Error::SetupWizard(SetupWizardErrorKind::ConfigFile(
-                ConfigFileErrorKind::FileWrite(full_config_path.clone()),
-            ))
+        .map_err(|source| {
+            config_file_error(ConfigFileErrorKind::FileWrite {
+                path: config_file_path.clone(),
+                source,
+            })
         })?;
 
     Ok(())
 }
+
+fn config_file_error(kind: ConfigFileErrorKind) -> Error {
+    Error::SetupWizard(SetupWizardErrorKind::ConfigFile(kind))
+}
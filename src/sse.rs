@@ -0,0 +1,160 @@
+use std::{fs, io, path::PathBuf};
+
+use aws_sdk_s3::{
+    operation::{
+        get_object::builders::GetObjectFluentBuilder,
+        head_object::builders::HeadObjectFluentBuilder,
+        put_object::builders::PutObjectFluentBuilder,
+    },
+    types::ServerSideEncryption as Algorithm,
+};
+use data_encoding::BASE64;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+// SSE-C only supports AES-256, with a key of exactly this many bytes.
+const CUSTOMER_KEY_LEN: usize = 32;
+
+/// The `[server_side_encryption]` section of the config file.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SseConfig {
+    /// SSE-S3, with keys managed by S3.
+    S3,
+    /// SSE-KMS, with the bucket's default KMS key unless `key_id` is set.
+    Kms {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        key_id: Option<String>,
+        #[serde(default)]
+        bucket_key: bool,
+    },
+    /// SSE-C, with a 256-bit key given in base64 or as the raw bytes of a keyfile.
+    Customer {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        key: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        keyfile: Option<PathBuf>,
+    },
+}
+
+#[derive(Error, Debug)]
+pub enum InvalidSse {
+    #[error("Set either `key` or `keyfile` for customer-provided keys, not both")]
+    KeySource,
+
+    #[error("Customer-provided keys must be 32 bytes, got {0}")]
+    KeyLength(usize),
+
+    #[error("The customer-provided key is not valid base64")]
+    KeyEncoding(#[source] data_encoding::DecodeError),
+
+    #[error("Could not read keyfile at path: `{}`", path.display())]
+    Keyfile { path: PathBuf, source: io::Error },
+}
+
+/// A customer-provided key in the form S3 expects it in the request headers.
+#[derive(Clone, Debug)]
+pub struct CustomerKey {
+    key: String,
+    md5: String,
+}
+
+/// How objects are encrypted at rest by S3. Applied to every upload, and for SSE-C to every
+/// download and metadata request too, since S3 cannot read those objects without the key.
+#[derive(Clone, Debug)]
+pub enum ServerSideEncryption {
+    S3,
+    Kms {
+        key_id: Option<String>,
+        bucket_key: bool,
+    },
+    Customer(CustomerKey),
+}
+
+impl ServerSideEncryption {
+    pub fn new(config: &SseConfig) -> Result<Self, InvalidSse> {
+        Ok(match config {
+            SseConfig::S3 => ServerSideEncryption::S3,
+            SseConfig::Kms { key_id, bucket_key } => ServerSideEncryption::Kms {
+                key_id: key_id.clone(),
+                bucket_key: *bucket_key,
+            },
+            SseConfig::Customer { key, keyfile } => {
+                let key = match (key, keyfile) {
+                    (Some(key), None) => BASE64
+                        .decode(key.trim().as_bytes())
+                        .map_err(InvalidSse::KeyEncoding)?,
+                    (None, Some(path)) => fs::read(path).map_err(|source| InvalidSse::Keyfile {
+                        path: path.clone(),
+                        source,
+                    })?,
+                    _ => return Err(InvalidSse::KeySource),
+                };
+                if key.len() != CUSTOMER_KEY_LEN {
+                    return Err(InvalidSse::KeyLength(key.len()));
+                }
+                ServerSideEncryption::Customer(CustomerKey {
+                    key: BASE64.encode(&key),
+                    md5: BASE64.encode(&md5::compute(&key).0),
+                })
+            }
+        })
+    }
+
+    pub fn put(&self, request: PutObjectFluentBuilder) -> PutObjectFluentBuilder {
+        match self {
+            ServerSideEncryption::S3 => request.server_side_encryption(Algorithm::Aes256),
+            ServerSideEncryption::Kms { key_id, bucket_key } => request
+                .server_side_encryption(Algorithm::AwsKms)
+                .set_ssekms_key_id(key_id.clone())
+                .bucket_key_enabled(*bucket_key),
+            ServerSideEncryption::Customer(customer) => request
+                .sse_customer_algorithm("AES256")
+                .sse_customer_key(&customer.key)
+                .sse_customer_key_md5(&customer.md5),
+        }
+    }
+
+    pub fn get(&self, request: GetObjectFluentBuilder) -> GetObjectFluentBuilder {
+        match self {
+            ServerSideEncryption::Customer(customer) => request
+                .sse_customer_algorithm("AES256")
+                .sse_customer_key(&customer.key)
+                .sse_customer_key_md5(&customer.md5),
+            _ => request,
+        }
+    }
+
+    pub fn head(&self, request: HeadObjectFluentBuilder) -> HeadObjectFluentBuilder {
+        match self {
+            ServerSideEncryption::Customer(customer) => request
+                .sse_customer_algorithm("AES256")
+                .sse_customer_key(&customer.key)
+                .sse_customer_key_md5(&customer.md5),
+            _ => request,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_customer_key_must_be_256_bits() {
+        let config = |key: &[u8]| SseConfig::Customer {
+            key: Some(BASE64.encode(key)),
+            keyfile: None,
+        };
+        assert!(matches!(
+            ServerSideEncryption::new(&config(&[7; 16])),
+            Err(InvalidSse::KeyLength(16))
+        ));
+        let Ok(ServerSideEncryption::Customer(customer)) =
+            ServerSideEncryption::new(&config(&[7; 32]))
+        else {
+            panic!("a 32 byte key is valid");
+        };
+        assert_eq!(BASE64.decode(customer.md5.as_bytes()).unwrap().len(), 16);
+    }
+}
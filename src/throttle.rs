@@ -0,0 +1,259 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use aws_sdk_s3::primitives::{ByteStream, ByteStreamError, SdkBody};
+use aws_smithy_types::body::Error as BoxError;
+use bytes::Bytes;
+use http_body::{Body, Frame, SizeHint};
+use lazy_static::lazy_static;
+use tokio::time::{Instant, Sleep};
+
+use crate::logging::PROJECT_NAME;
+
+// Size of the slices a throttled body is cut into. Small enough that pacing stays smooth
+// at low rates without adding noticeable overhead at full speed.
+const CHUNK_SIZE: usize = 16 * 1024;
+
+lazy_static! {
+    pub static ref UPLOAD_LIMIT_ENV: String = format!("{}_UPLOAD_LIMIT", PROJECT_NAME.clone());
+    pub static ref DOWNLOAD_LIMIT_ENV: String = format!("{}_DOWNLOAD_LIMIT", PROJECT_NAME.clone());
+}
+
+/// Rate limits in KiB/s, as written in the `[throttle]` section of the config file.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ThrottleConfig {
+    pub upload_limit: Option<u64>,
+    pub download_limit: Option<u64>,
+}
+
+impl ThrottleConfig {
+    /// Global limits, read from `CYNC_UPLOAD_LIMIT` and `CYNC_DOWNLOAD_LIMIT`.
+    pub fn from_env() -> Self {
+        let read = |name: &str| std::env::var(name).ok().and_then(|v| v.parse().ok());
+        ThrottleConfig {
+            upload_limit: read(&UPLOAD_LIMIT_ENV),
+            download_limit: read(&DOWNLOAD_LIMIT_ENV),
+        }
+    }
+
+    /// Limits set for this sync pair take precedence over the global ones.
+    pub fn or(self, global: ThrottleConfig) -> Self {
+        ThrottleConfig {
+            upload_limit: self.upload_limit.or(global.upload_limit),
+            download_limit: self.download_limit.or(global.download_limit),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Direction {
+    Upload,
+    Download,
+}
+
+/// Shared bandwidth limiter for every transfer made by an `S3Client`.
+#[derive(Clone)]
+pub struct Throttle {
+    upload: Option<Arc<RateLimiter>>,
+    download: Option<Arc<RateLimiter>>,
+    enabled: Arc<AtomicBool>,
+}
+
+impl Throttle {
+    pub fn new(config: ThrottleConfig) -> Self {
+        let limiter = |kib: u64| Arc::new(RateLimiter::new(kib * 1024));
+        Throttle {
+            upload: config.upload_limit.filter(|l| *l > 0).map(limiter),
+            download: config.download_limit.filter(|l| *l > 0).map(limiter),
+            enabled: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.upload.is_some() || self.download.is_some()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Switches between throttled and full-speed transfers. Returns the new state.
+    pub fn toggle(&self) -> bool {
+        !self.enabled.fetch_xor(true, Ordering::Relaxed)
+    }
+
+    /// How long the caller has to wait before it may transfer `bytes` in `direction`.
+    fn reserve(&self, direction: Direction, bytes: usize) -> Duration {
+        if !self.is_enabled() {
+            return Duration::ZERO;
+        }
+        let limiter = match direction {
+            Direction::Upload => &self.upload,
+            Direction::Download => &self.download,
+        };
+        limiter
+            .as_ref()
+            .map(|limiter| limiter.reserve(bytes))
+            .unwrap_or_default()
+    }
+
+    /// Wraps an upload body so that it is streamed out no faster than the upload limit.
+    /// The wrapped body stays retryable if the original one was.
+    pub fn upload_body(&self, body: ByteStream) -> ByteStream {
+        if self.upload.is_none() {
+            return body;
+        }
+        let throttle = self.clone();
+        body.map(move |inner| SdkBody::from_body_1_x(ThrottledBody::new(inner, throttle.clone())))
+    }
+
+    /// Reads a download body to the end, pausing between chunks to respect the download limit.
+    pub async fn download_body(&self, mut body: ByteStream) -> Result<Vec<u8>, ByteStreamError> {
+        let mut contents = Vec::new();
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk?;
+            let wait = self.reserve(Direction::Download, chunk.len());
+            if !wait.is_zero() {
+                tokio::time::sleep(wait).await;
+            }
+            contents.extend_from_slice(&chunk);
+        }
+        Ok(contents)
+    }
+}
+
+/// Token bucket in the "virtual scheduling" form: every reservation pushes back the
+/// earliest time the next one may start.
+struct RateLimiter {
+    bytes_per_sec: u64,
+    next_free: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    fn new(bytes_per_sec: u64) -> Self {
+        RateLimiter {
+            bytes_per_sec,
+            next_free: Mutex::new(Instant::now()),
+        }
+    }
+
+    fn reserve(&self, bytes: usize) -> Duration {
+        let now = Instant::now();
+        let mut next_free = self.next_free.lock().expect("rate limiter lock poisoned");
+        let start = (*next_free).max(now);
+        *next_free = start + Duration::from_secs_f64(bytes as f64 / self.bytes_per_sec as f64);
+        start - now
+    }
+}
+
+struct ThrottledBody {
+    inner: SdkBody,
+    throttle: Throttle,
+    buffered: Bytes,
+    pending: Option<(Bytes, Pin<Box<Sleep>>)>,
+}
+
+impl ThrottledBody {
+    fn new(inner: SdkBody, throttle: Throttle) -> Self {
+        ThrottledBody {
+            inner,
+            throttle,
+            buffered: Bytes::new(),
+            pending: None,
+        }
+    }
+}
+
+impl Body for ThrottledBody {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        loop {
+            if let Some((_, sleep)) = self.pending.as_mut() {
+                ready!(sleep.as_mut().poll(cx));
+                let (chunk, _) = self.pending.take().expect("pending chunk is set");
+                return Poll::Ready(Some(Ok(Frame::data(chunk))));
+            }
+
+            if !self.buffered.is_empty() {
+                let len = CHUNK_SIZE.min(self.buffered.len());
+                let chunk = self.buffered.split_to(len);
+                let wait = self.throttle.reserve(Direction::Upload, len);
+                if wait.is_zero() {
+                    return Poll::Ready(Some(Ok(Frame::data(chunk))));
+                }
+                self.pending = Some((chunk, Box::pin(tokio::time::sleep(wait))));
+                continue;
+            }
+
+            match ready!(Pin::new(&mut self.inner).poll_frame(cx)) {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(data) => self.buffered = data,
+                    Err(frame) => return Poll::Ready(Some(Ok(frame))),
+                },
+                Some(Err(err)) => return Poll::Ready(Some(Err(err))),
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.pending.is_none() && self.buffered.is_empty() && Body::is_end_stream(&self.inner)
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let held = (self.buffered.len() + self.pending.as_ref().map_or(0, |(chunk, _)| chunk.len()))
+            as u64;
+        let inner = Body::size_hint(&self.inner);
+        let mut hint = SizeHint::new();
+        hint.set_lower(inner.lower() + held);
+        if let Some(upper) = inner.upper() {
+            hint.set_upper(upper + held);
+        }
+        hint
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rate_limiter_spaces_out_reservations() {
+        let limiter = RateLimiter::new(1024);
+        assert_eq!(limiter.reserve(512), Duration::ZERO);
+        let wait = limiter.reserve(512);
+        assert!(wait > Duration::from_millis(450) && wait <= Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_pair_limits_override_global_limits() {
+        let pair = ThrottleConfig {
+            upload_limit: Some(10),
+            download_limit: None,
+        };
+        let global = ThrottleConfig {
+            upload_limit: Some(20),
+            download_limit: Some(30),
+        };
+        assert_eq!(
+            pair.or(global),
+            ThrottleConfig {
+                upload_limit: Some(10),
+                download_limit: Some(30),
+            }
+        );
+    }
+}
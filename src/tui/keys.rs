@@ -0,0 +1,440 @@
+use std::{collections::HashMap, fmt};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Something the user can do with a remappable key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Top,
+    Bottom,
+    Select,
+    Back,
+    Quit,
+    Help,
+    Mark,
+    Visual,
+    MarkByStatus,
+    ClearMarks,
+    Search,
+    FilterOutOfSync,
+    FilterLocalOnly,
+    FilterRemoteOnly,
+    FilterConflicts,
+    ClearFilter,
+    Collapse,
+    Expand,
+    Throttle,
+    Preview,
+    Sort,
+    ReverseSort,
+    Push,
+    Pull,
+    Delete,
+    Compare,
+    Resolve,
+    KeepLocal,
+    KeepRemote,
+    KeepBoth,
+    Merge,
+    DiffLayout,
+    History,
+    DeletedFiles,
+    Restore,
+    Trash,
+}
+
+impl Action {
+    const ALL: [Action; 40] = [
+        Action::Up,
+        Action::Down,
+        Action::PageUp,
+        Action::PageDown,
+        Action::Top,
+        Action::Bottom,
+        Action::Select,
+        Action::Back,
+        Action::Quit,
+        Action::Help,
+        Action::Mark,
+        Action::Visual,
+        Action::MarkByStatus,
+        Action::ClearMarks,
+        Action::Search,
+        Action::FilterOutOfSync,
+        Action::FilterLocalOnly,
+        Action::FilterRemoteOnly,
+        Action::FilterConflicts,
+        Action::ClearFilter,
+        Action::Collapse,
+        Action::Expand,
+        Action::Throttle,
+        Action::Preview,
+        Action::Sort,
+        Action::ReverseSort,
+        Action::Push,
+        Action::Pull,
+        Action::Delete,
+        Action::Compare,
+        Action::Resolve,
+        Action::KeepLocal,
+        Action::KeepRemote,
+        Action::KeepBoth,
+        Action::Merge,
+        Action::DiffLayout,
+        Action::History,
+        Action::DeletedFiles,
+        Action::Restore,
+        Action::Trash,
+    ];
+
+    fn default_keys(self) -> &'static [&'static str] {
+        match self {
+            Action::Up => &["k", "Up"],
+            Action::Down => &["j", "Down"],
+            Action::PageUp => &["PageUp", "ctrl-u"],
+            Action::PageDown => &["PageDown", "ctrl-d"],
+            Action::Top => &["gg", "Home"],
+            Action::Bottom => &["G", "End"],
+            Action::Select => &["Enter"],
+            Action::Back => &["q", "Esc"],
+            Action::Quit => &["q"],
+            Action::Help => &["?"],
+            Action::Mark => &["Space"],
+            Action::Visual => &["v"],
+            Action::MarkByStatus => &["a"],
+            Action::ClearMarks => &["Esc"],
+            Action::Search => &["/"],
+            Action::FilterOutOfSync => &["1"],
+            Action::FilterLocalOnly => &["2"],
+            Action::FilterRemoteOnly => &["3"],
+            Action::FilterConflicts => &["4"],
+            Action::ClearFilter => &["0"],
+            Action::Collapse => &["h", "Left"],
+            Action::Expand => &["l", "Right"],
+            Action::Throttle => &["b"],
+            Action::Preview => &["p"],
+            Action::Sort => &["s"],
+            Action::ReverseSort => &["S"],
+            Action::Push => &["t"],
+            Action::Pull => &["f"],
+            Action::Delete => &["d"],
+            Action::Compare => &["c"],
+            Action::Resolve => &["r"],
+            Action::KeepLocal => &["l"],
+            Action::KeepRemote => &["r"],
+            Action::KeepBoth => &["b"],
+            Action::Merge => &["m"],
+            Action::DiffLayout => &["s"],
+            Action::History => &["H"],
+            Action::DeletedFiles => &["D"],
+            Action::Restore => &["r"],
+            Action::Trash => &["T"],
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::Up => "Move up",
+            Action::Down => "Move down",
+            Action::PageUp => "Page up",
+            Action::PageDown => "Page down",
+            Action::Top => "Go to the top",
+            Action::Bottom => "Go to the bottom",
+            Action::Select => "Open the action menu",
+            Action::Back => "Go back",
+            Action::Quit => "Quit",
+            Action::Help => "Show this help",
+            Action::Mark => "Mark or unmark",
+            Action::Visual => "Mark a range",
+            Action::MarkByStatus => "Mark files by status",
+            Action::ClearMarks => "Clear marks",
+            Action::Search => "Search paths",
+            Action::FilterOutOfSync => "Show files out of sync",
+            Action::FilterLocalOnly => "Show local only files",
+            Action::FilterRemoteOnly => "Show remote only files",
+            Action::FilterConflicts => "Show conflicts",
+            Action::ClearFilter => "Clear search and filters",
+            Action::Collapse => "Collapse folder",
+            Action::Expand => "Expand folder",
+            Action::Throttle => "Toggle bandwidth throttling",
+            Action::Preview => "Toggle preview pane",
+            Action::Sort => "Sort by next column",
+            Action::ReverseSort => "Reverse sort order",
+            Action::Push => "Push to remote",
+            Action::Pull => "Pull from remote",
+            Action::Delete => "Delete",
+            Action::Compare => "Compare local and remote",
+            Action::Resolve => "Resolve conflict",
+            Action::KeepLocal => "Keep local version",
+            Action::KeepRemote => "Keep remote version",
+            Action::KeepBoth => "Keep both versions",
+            Action::Merge => "Three-way merge",
+            Action::DiffLayout => "Toggle side-by-side",
+            Action::History => "Show earlier versions",
+            Action::DeletedFiles => "Show files deleted from the remote",
+            Action::Restore => "Restore the selected version or file",
+            Action::Trash => "Show local files that were overwritten or deleted",
+        }
+    }
+}
+
+/// The `[keys]` section of the config file, replacing the default keys of each listed action.
+pub type KeyConfig = HashMap<Action, Vec<String>>;
+
+#[derive(Error, Debug)]
+#[error("Unknown key `{key}` for `{action:?}`")]
+pub struct InvalidKey {
+    key: String,
+    action: Action,
+}
+
+/// A single key press, with Shift folded into the character.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyPress {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl From<KeyEvent> for KeyPress {
+    fn from(event: KeyEvent) -> Self {
+        let modifiers = match event.code {
+            KeyCode::Char(_) => event.modifiers - KeyModifiers::SHIFT,
+            _ => event.modifiers,
+        };
+        KeyPress {
+            code: event.code,
+            modifiers,
+        }
+    }
+}
+
+const NAMED_KEYS: [(&str, KeyCode); 13] = [
+    ("Space", KeyCode::Char(' ')),
+    ("Enter", KeyCode::Enter),
+    ("Esc", KeyCode::Esc),
+    ("Tab", KeyCode::Tab),
+    ("Backspace", KeyCode::Backspace),
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+];
+
+impl KeyPress {
+    fn plain(code: KeyCode) -> Self {
+        KeyPress {
+            code,
+            modifiers: KeyModifiers::NONE,
+        }
+    }
+
+    /// Parses a key as written in the config file: a key name such as `PageDown`, a
+    /// `ctrl-` or `alt-` prefixed key, or a sequence of characters such as `gg`.
+    fn parse_sequence(key: &str) -> Option<Vec<KeyPress>> {
+        if let Some((_, code)) = NAMED_KEYS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(key))
+        {
+            return Some(vec![KeyPress::plain(*code)]);
+        }
+        for (prefix, modifier) in [
+            ("ctrl-", KeyModifiers::CONTROL),
+            ("alt-", KeyModifiers::ALT),
+        ] {
+            if let Some(rest) = key.strip_prefix(prefix) {
+                let [mut press] = KeyPress::parse_sequence(rest)?[..] else {
+                    return None;
+                };
+                press.modifiers |= modifier;
+                return Some(vec![press]);
+            }
+        }
+        let sequence = key
+            .chars()
+            .map(|c| KeyPress::plain(KeyCode::Char(c)))
+            .collect::<Vec<_>>();
+        (!sequence.is_empty()).then_some(sequence)
+    }
+}
+
+impl fmt::Display for KeyPress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let named = NAMED_KEYS.iter().find(|(_, code)| *code == self.code);
+        let key = match (named, self.code) {
+            (Some((name, _)), _) => name.to_string(),
+            (None, KeyCode::Char(c)) => c.to_string(),
+            (None, code) => format!("{code:?}"),
+        };
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "<C-{key}>")
+        } else if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "<A-{key}>")
+        } else if named.is_some() {
+            write!(f, "<{key}>")
+        } else {
+            write!(f, "{key}")
+        }
+    }
+}
+
+/// Every key sequence bound to an action, defaults overridden by the config file.
+pub struct KeyBindings {
+    bindings: Vec<(Vec<KeyPress>, Action)>,
+}
+
+pub enum Lookup {
+    Action(Action),
+    /// The keys so far start a longer sequence, wait for the next one.
+    Pending,
+    None,
+}
+
+impl KeyBindings {
+    pub fn new(config: &KeyConfig) -> Result<Self, InvalidKey> {
+        let mut bindings = Vec::new();
+        for action in Action::ALL {
+            let keys = match config.get(&action) {
+                Some(keys) => keys.iter().map(String::as_str).collect::<Vec<_>>(),
+                None => action.default_keys().to_vec(),
+            };
+            for key in keys {
+                let sequence = KeyPress::parse_sequence(key).ok_or_else(|| InvalidKey {
+                    key: key.to_string(),
+                    action,
+                })?;
+                bindings.push((sequence, action));
+            }
+        }
+        Ok(KeyBindings { bindings })
+    }
+
+    /// Looks up the keys pressed so far among the bindings of `actions`.
+    pub fn lookup(&self, pressed: &[KeyPress], actions: &[Action]) -> Lookup {
+        let mut candidates = self
+            .bindings
+            .iter()
+            .filter(|(sequence, action)| actions.contains(action) && sequence.starts_with(pressed));
+        let mut pending = false;
+        let exact = candidates.find_map(|(sequence, action)| {
+            pending = true;
+            (sequence.len() == pressed.len()).then_some(*action)
+        });
+        match exact {
+            Some(action) => Lookup::Action(action),
+            None if pending => Lookup::Pending,
+            None => Lookup::None,
+        }
+    }
+
+    /// The first key of `action`, for short hints.
+    pub fn primary(&self, action: Action) -> String {
+        self.bindings
+            .iter()
+            .find(|(_, bound)| *bound == action)
+            .map(|(sequence, _)| sequence.iter().map(KeyPress::to_string).collect())
+            .unwrap_or_default()
+    }
+
+    /// How the keys of `action` are written in the help and footer, e.g. `j/<Down>`.
+    pub fn describe(&self, action: Action) -> String {
+        self.bindings
+            .iter()
+            .filter(|(_, bound)| *bound == action)
+            .map(|(sequence, _)| sequence.iter().map(KeyPress::to_string).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings::new(&KeyConfig::new()).expect("default keys are valid")
+    }
+}
+
+/// Collects key presses until they form a bound sequence.
+#[derive(Default)]
+pub struct KeyReader {
+    pressed: Vec<KeyPress>,
+}
+
+impl KeyReader {
+    pub fn read(
+        &mut self,
+        bindings: &KeyBindings,
+        key: KeyEvent,
+        actions: &[Action],
+    ) -> Option<Action> {
+        self.pressed.push(KeyPress::from(key));
+        match bindings.lookup(&self.pressed, actions) {
+            Lookup::Action(action) => {
+                self.pressed.clear();
+                Some(action)
+            }
+            Lookup::Pending => None,
+            // A broken sequence still lets its last key start a new one.
+            Lookup::None if self.pressed.len() > 1 => {
+                self.pressed.clear();
+                self.read(bindings, key, actions)
+            }
+            Lookup::None => {
+                self.pressed.clear();
+                None
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.pressed.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(c: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_key_sequences() {
+        let config = KeyConfig::from([(Action::Bottom, vec![String::from("ctrl-e")])]);
+        let bindings = KeyBindings::new(&config).unwrap();
+        let actions = [Action::Top, Action::Bottom, Action::Down];
+        let mut reader = KeyReader::default();
+
+        assert_eq!(reader.read(&bindings, press('g'), &actions), None);
+        assert_eq!(
+            reader.read(&bindings, press('g'), &actions),
+            Some(Action::Top)
+        );
+        assert_eq!(reader.read(&bindings, press('g'), &actions), None);
+        assert_eq!(
+            reader.read(&bindings, press('j'), &actions),
+            Some(Action::Down)
+        );
+        assert_eq!(reader.read(&bindings, press('G'), &actions), None);
+        assert_eq!(
+            reader.read(
+                &bindings,
+                KeyEvent::new(KeyCode::Char('e'), KeyModifiers::CONTROL),
+                &actions
+            ),
+            Some(Action::Bottom)
+        );
+        assert_eq!(bindings.describe(Action::Top), "gg/<Home>");
+        assert!(KeyBindings::new(&KeyConfig::from([(Action::Up, vec![String::new()])])).is_err());
+    }
+}
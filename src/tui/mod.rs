@@ -1,96 +1,451 @@
-use std::io::Stderr;
+use std::{io::Stderr, sync::Arc};
 
-use crossterm::event::{self, Event, KeyCode};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
 use ratatui::{backend::CrosstermBackend, Terminal};
 
 use crate::{
-    cync::{file_viewer::FileKind, Cync, Mode},
+    cync::{conflict::Merge, file_viewer::FileStatus, filter::StatusFilter, Cync, Mode, Operation},
     error::{Error, TuiErrorKind},
+    util::{edit_in_editor, set_mouse_capture},
 };
+use keys::{Action, KeyReader};
+use mouse::{handle_mouse, ClickMap, ClickTracker};
 use ui::ui;
 
+pub mod keys;
+mod mouse;
+pub mod theme;
 mod ui;
 
+const NAVIGATION: [Action; 6] = [
+    Action::Up,
+    Action::Down,
+    Action::PageUp,
+    Action::PageDown,
+    Action::Top,
+    Action::Bottom,
+];
+
 pub async fn run_tui(
     terminal: &mut Terminal<CrosstermBackend<Stderr>>,
     app: &mut Cync,
 ) -> Result<(), Error> {
+    set_mouse_capture(app.config.mouse())?;
+    let mut keys = KeyReader::default();
+    let mut clicks = ClickMap::default();
+    let mut tracker = ClickTracker::default();
     loop {
+        app.refresh_preview().await;
         terminal
             .draw(|frame| {
-                ui(frame, app);
+                clicks = ui(frame, app);
             })
-            .map_err(|_| Error::Tui(TuiErrorKind::Drawing))?;
+            .map_err(|source| Error::Tui {
+                kind: TuiErrorKind::Drawing,
+                source,
+            })?;
+
+        let event = event::read().map_err(|source| Error::Tui {
+            kind: TuiErrorKind::KeyboardEvent,
+            source,
+        })?;
+        if let Event::Mouse(mouse) = event {
+            if handle_mouse(terminal, app, &clicks, &mut tracker, mouse).await? {
+                return Ok(());
+            }
+            continue;
+        }
+        if let Event::Key(key) = event {
+            if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                return Ok(());
+            }
 
-        if let Event::Key(key) =
-            event::read().map_err(|_| Error::Tui(TuiErrorKind::KeyboardEvent))?
-        {
-            match &app.mode {
-                Mode::NoFilesFound => return Ok(()),
-                Mode::Default => match key.code {
-                    KeyCode::Char('q') => return Ok(()),
-                    KeyCode::Char('j') => app.next_file(),
-                    KeyCode::Char('k') => app.prev_file(),
-                    KeyCode::Enter => app.select_file(app.table_state.selected().unwrap()),
+            if app.error_popup.is_some() {
+                match key.code {
+                    KeyCode::Char('r') => app.retry_failed_operation().await?,
+                    KeyCode::Char('q') | KeyCode::Esc | KeyCode::Enter => app.dismiss_error(),
                     _ => {}
-                },
-                // TODO: Add some sort of loader while awaiting
-                Mode::PendingAction(kind) => match kind {
-                    FileKind::OnlyInRemote { .. } => match key.code {
-                        KeyCode::Char('f') => {
-                            app.pull_file_from_remote(app.selected_file.unwrap())?;
-                            app.reload_files().await?;
-                            app.selected_file = None;
-                            app.mode = Mode::Default;
-                        }
-                        KeyCode::Char('q') => {
-                            app.selected_file = None;
-                            app.mode = Mode::Default;
-                        }
-                        _ => {}
-                    },
-                    FileKind::OnlyInLocal { .. } => match key.code {
-                        KeyCode::Char('t') => {
-                            app.push_file_to_remote(app.selected_file.unwrap()).await?;
-                            app.reload_files().await?;
-                            app.mode = Mode::Default;
-                            app.selected_file = None;
-                        }
-                        KeyCode::Char('q') => {
-                            app.selected_file = None;
-                            app.mode = Mode::Default;
-                        }
-                        _ => {}
-                    },
-                    FileKind::ExistsInBoth {
-                        local_hash,
-                        remote_hash,
-                        ..
-                    } => match key.code {
-                        KeyCode::Char('f') if local_hash != remote_hash => {
-                            if local_hash != remote_hash {
-                                app.pull_file_from_remote(app.selected_file.unwrap())?;
-                                app.reload_files().await?;
-                                app.selected_file = None;
-                                app.mode = Mode::Default;
-                            }
-                        }
-                        KeyCode::Char('t') if local_hash != remote_hash => {
-                            if local_hash != remote_hash {
-                                app.push_file_to_remote(app.selected_file.unwrap()).await?;
-                                app.reload_files().await?;
-                                app.selected_file = None;
-                                app.mode = Mode::Default;
-                            }
-                        }
-                        KeyCode::Char('q') => {
-                            app.selected_file = None;
-                            app.mode = Mode::Default;
+                }
+                continue;
+            }
+
+            if app.show_help {
+                app.show_help = false;
+                continue;
+            }
+
+            if let Some(quit) = handle_fixed_key(app, key).await? {
+                if quit {
+                    return Ok(());
+                }
+                keys.clear();
+                continue;
+            }
+
+            let config = Arc::clone(&app.config);
+            if let Some(action) = keys.read(config.keys(), key, &available_actions(app)) {
+                if handle_action(terminal, app, action).await? {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Handles the modes whose keys are not remappable, such as typing a search query. Returns
+/// whether to quit, or `None` if the current mode uses the key bindings.
+async fn handle_fixed_key(app: &mut Cync, key: KeyEvent) -> Result<Option<bool>, Error> {
+    match &app.mode {
+        Mode::NoFilesFound => return Ok(Some(true)),
+        Mode::Search => match key.code {
+            KeyCode::Char(c) => app.push_search_char(c),
+            KeyCode::Backspace => app.pop_search_char(),
+            KeyCode::Down => app.next_file(),
+            KeyCode::Up => app.prev_file(),
+            KeyCode::Enter => app.finish_search(),
+            KeyCode::Esc => app.cancel_search(),
+            _ => {}
+        },
+        Mode::SelectByStatus => match key.code {
+            KeyCode::Char('a') => app.mark_by_status(|_| true),
+            KeyCode::Char('o') => app.mark_by_status(|status| status != FileStatus::InSync),
+            KeyCode::Char('l') => app.mark_by_status(|status| status == FileStatus::OnlyInLocal),
+            KeyCode::Char('r') => app.mark_by_status(|status| status == FileStatus::OnlyInRemote),
+            KeyCode::Char('c') => app.mark_by_status(|status| status == FileStatus::Changed),
+            KeyCode::Char('n') => {
+                app.clear_marks();
+                app.mode = Mode::Default;
+            }
+            KeyCode::Char('?') => app.show_help = true,
+            KeyCode::Esc | KeyCode::Char('q') => app.mode = Mode::Default,
+            _ => {}
+        },
+        Mode::ConfirmDelete(paths) => match key.code {
+            KeyCode::Char('y') => {
+                let paths = paths.clone();
+                app.perform(Operation::Delete(paths)).await?;
+            }
+            KeyCode::Char('?') => app.show_help = true,
+            KeyCode::Char('n') | KeyCode::Char('q') | KeyCode::Esc => {
+                app.mode = Mode::Default;
+            }
+            _ => {}
+        },
+        _ => return Ok(None),
+    }
+    Ok(Some(false))
+}
+
+/// Keys that only apply in some modes, in the order they are listed in the help overlay.
+pub fn fixed_keys(mode: &Mode) -> &'static [(&'static str, &'static str)] {
+    match mode {
+        Mode::Search => &[
+            ("<any>", "Type the search query"),
+            ("<Backspace>", "Delete a character"),
+            ("<Up>/<Down>", "Move"),
+            ("<Enter>", "Keep the search as a filter"),
+            ("<Esc>", "Cancel the search"),
+        ],
+        Mode::SelectByStatus => &[
+            ("a", "Mark all files"),
+            ("o", "Mark files out of sync"),
+            ("l", "Mark local only files"),
+            ("r", "Mark remote only files"),
+            ("c", "Mark changed files"),
+            ("n", "Clear marks"),
+            ("q/<Esc>", "Go back"),
+        ],
+        Mode::ConfirmDelete(_) => &[("y", "Delete"), ("n/q/<Esc>", "Go back")],
+        _ => &[("<C-c>", "Quit from anywhere")],
+    }
+}
+
+/// The remappable actions that do something in the current mode.
+pub fn available_actions(app: &Cync) -> Vec<Action> {
+    let mut actions = match &app.mode {
+        Mode::Default => {
+            let mut actions = NAVIGATION.to_vec();
+            actions.extend([
+                Action::Select,
+                Action::Mark,
+                Action::Visual,
+                Action::MarkByStatus,
+                Action::ClearMarks,
+                Action::Search,
+                Action::FilterOutOfSync,
+                Action::FilterLocalOnly,
+                Action::FilterRemoteOnly,
+                Action::FilterConflicts,
+                Action::ClearFilter,
+                Action::Collapse,
+                Action::Expand,
+                Action::Preview,
+                Action::Sort,
+                Action::ReverseSort,
+                Action::History,
+                Action::DeletedFiles,
+                Action::Trash,
+            ]);
+            if app.config.aws_client().throttle().is_configured() {
+                actions.push(Action::Throttle);
+            }
+            actions.push(Action::Quit);
+            actions
+        }
+        Mode::Visual(_) => {
+            let mut actions = NAVIGATION.to_vec();
+            actions.extend([Action::Visual, Action::Mark, Action::Back]);
+            actions
+        }
+        Mode::Diff => {
+            let mut actions = NAVIGATION.to_vec();
+            actions.extend([Action::DiffLayout, Action::Back]);
+            actions
+        }
+        Mode::PendingAction(kind) => {
+            let mut actions = Vec::new();
+            if kind.can_push() {
+                actions.push(Action::Push);
+            }
+            if kind.can_pull() {
+                actions.push(Action::Pull);
+            }
+            if kind.status() == FileStatus::Changed {
+                actions.extend([Action::Resolve, Action::Compare]);
+            }
+            actions.extend([Action::History, Action::Delete, Action::Back]);
+            actions
+        }
+        Mode::PendingFolderAction(_) | Mode::PendingBatchAction => {
+            let (push, pull) = match &app.mode {
+                Mode::PendingFolderAction(folder) => app.folder_actions(folder),
+                _ => app.batch_actions(),
+            };
+            let mut actions = Vec::new();
+            if !push.is_empty() {
+                actions.push(Action::Push);
+            }
+            if !pull.is_empty() {
+                actions.push(Action::Pull);
+            }
+            actions.extend([Action::Delete, Action::Back]);
+            actions
+        }
+        Mode::ResolveConflict { can_merge } => {
+            let mut actions = vec![Action::KeepLocal, Action::KeepRemote, Action::KeepBoth];
+            if *can_merge {
+                actions.push(Action::Merge);
+            }
+            actions.push(Action::Back);
+            actions
+        }
+        Mode::History => {
+            let mut actions = NAVIGATION.to_vec();
+            if let Some(version) = app.history.as_ref().and_then(|history| history.selected()) {
+                if version.can_restore() {
+                    actions.push(Action::Restore);
+                }
+                if version.can_download() {
+                    actions.push(Action::Pull);
+                }
+            }
+            actions.push(Action::Back);
+            actions
+        }
+        Mode::Trash => {
+            let mut actions = NAVIGATION.to_vec();
+            if app
+                .trash
+                .as_ref()
+                .and_then(|trash| trash.selected())
+                .is_some()
+            {
+                actions.push(Action::Restore);
+            }
+            actions.push(Action::Back);
+            actions
+        }
+        Mode::Search | Mode::SelectByStatus | Mode::ConfirmDelete(_) | Mode::NoFilesFound => {
+            return Vec::new()
+        }
+    };
+    actions.push(Action::Help);
+    actions
+}
+
+/// Runs `action` in the current mode. Returns whether to quit.
+// TODO: Add some sort of loader while awaiting
+async fn handle_action(
+    terminal: &mut Terminal<CrosstermBackend<Stderr>>,
+    app: &mut Cync,
+    action: Action,
+) -> Result<bool, Error> {
+    if action == Action::Help {
+        app.show_help = true;
+        return Ok(false);
+    }
+
+    match &app.mode {
+        Mode::Diff => {
+            let page = app.page_size as isize;
+            let Some(diff) = app.diff.as_mut() else {
+                app.close_diff();
+                return Ok(false);
+            };
+            match action {
+                Action::Down => diff.scroll_by(1),
+                Action::Up => diff.scroll_by(-1),
+                Action::PageDown => diff.scroll_by(page),
+                Action::PageUp => diff.scroll_by(-page),
+                Action::Top => diff.scroll_by(isize::MIN),
+                Action::Bottom => diff.scroll_by(isize::MAX),
+                Action::DiffLayout => diff.toggle_layout(),
+                Action::Back => app.close_diff(),
+                _ => {}
+            }
+        }
+        Mode::History => {
+            let page = app.page_size as isize;
+            let Some(history) = app.history.as_mut() else {
+                app.close_history();
+                return Ok(false);
+            };
+            match action {
+                Action::Down => history.move_by(1),
+                Action::Up => history.move_by(-1),
+                Action::PageDown => history.move_by(page),
+                Action::PageUp => history.move_by(-page),
+                Action::Top => history.move_by(isize::MIN),
+                Action::Bottom => history.move_by(isize::MAX),
+                Action::Restore => app.restore_selected_version().await?,
+                Action::Pull => app.download_selected_version().await?,
+                Action::Back => app.close_history(),
+                _ => {}
+            }
+        }
+        Mode::Trash => {
+            let page = app.page_size as isize;
+            let Some(trash) = app.trash.as_mut() else {
+                app.close_trash();
+                return Ok(false);
+            };
+            match action {
+                Action::Down => trash.move_by(1),
+                Action::Up => trash.move_by(-1),
+                Action::PageDown => trash.move_by(page),
+                Action::PageUp => trash.move_by(-page),
+                Action::Top => trash.move_by(isize::MIN),
+                Action::Bottom => trash.move_by(isize::MAX),
+                Action::Restore => app.restore_selected_trash().await?,
+                Action::Back => app.close_trash(),
+                _ => {}
+            }
+        }
+        Mode::Default | Mode::Visual(_) if NAVIGATION.contains(&action) => {
+            let page = app.page_size as isize;
+            match action {
+                Action::Down => app.next_file(),
+                Action::Up => app.prev_file(),
+                Action::PageDown => app.move_cursor(page),
+                Action::PageUp => app.move_cursor(-page),
+                Action::Top => app.move_cursor(isize::MIN),
+                _ => app.move_cursor(isize::MAX),
+            }
+        }
+        Mode::Default => match action {
+            Action::Quit => return Ok(true),
+            Action::Select if !app.marked.is_empty() => app.mode = Mode::PendingBatchAction,
+            Action::Select => {
+                if let Some(index) = app.table_state.selected() {
+                    app.select_file(index);
+                }
+            }
+            Action::Mark => app.toggle_mark(),
+            Action::Visual => app.start_visual(),
+            Action::MarkByStatus => app.mode = Mode::SelectByStatus,
+            Action::ClearMarks => app.clear_marks(),
+            Action::Search => app.start_search(),
+            Action::FilterOutOfSync => app.toggle_status_filter(StatusFilter::OutOfSync),
+            Action::FilterLocalOnly => app.toggle_status_filter(StatusFilter::OnlyLocal),
+            Action::FilterRemoteOnly => app.toggle_status_filter(StatusFilter::OnlyRemote),
+            Action::FilterConflicts => app.toggle_status_filter(StatusFilter::Conflicts),
+            Action::ClearFilter => app.clear_filter(),
+            Action::Collapse => app.collapse_folder(),
+            Action::Expand => app.expand_folder(),
+            Action::Throttle => app.toggle_throttle(),
+            Action::Preview => app.toggle_preview(),
+            Action::Sort => app.cycle_sort(),
+            Action::ReverseSort => app.reverse_sort(),
+            Action::History => app.open_history().await?,
+            Action::DeletedFiles => app.open_deleted_files().await?,
+            Action::Trash => app.open_trash()?,
+            _ => {}
+        },
+        Mode::Visual(_) => match action {
+            Action::Visual | Action::Mark => app.finish_visual(),
+            Action::Back => app.cancel_visual(),
+            _ => {}
+        },
+        Mode::PendingAction(_) => {
+            let path = app.selected_file.clone().unwrap();
+            match action {
+                Action::Push => app.perform(Operation::Push(vec![path])).await?,
+                Action::Pull => app.perform(Operation::Pull(vec![path])).await?,
+                Action::Compare => app.open_diff().await?,
+                Action::Resolve => app.start_conflict_resolution().await,
+                Action::History => app.open_history().await?,
+                Action::Delete => {
+                    app.selected_file = None;
+                    app.mode = Mode::ConfirmDelete(vec![path]);
+                }
+                Action::Back => {
+                    app.selected_file = None;
+                    app.mode = Mode::Default;
+                }
+                _ => {}
+            }
+        }
+        Mode::PendingFolderAction(_) | Mode::PendingBatchAction => {
+            let (push, pull) = match &app.mode {
+                Mode::PendingFolderAction(folder) => app.folder_actions(folder),
+                _ => app.batch_actions(),
+            };
+            match action {
+                Action::Push => app.perform(Operation::Push(push)).await?,
+                Action::Pull => app.perform(Operation::Pull(pull)).await?,
+                Action::Delete => {
+                    let paths = match &app.mode {
+                        Mode::PendingFolderAction(folder) => app.folder_files(folder),
+                        _ => app.marked.iter().cloned().collect(),
+                    };
+                    app.mode = Mode::ConfirmDelete(paths);
+                }
+                Action::Back => app.mode = Mode::Default,
+                _ => {}
+            }
+        }
+        Mode::ResolveConflict { .. } => {
+            let path = app.selected_file.clone().unwrap();
+            match action {
+                Action::KeepLocal => app.perform(Operation::Push(vec![path])).await?,
+                Action::KeepRemote => app.perform(Operation::Pull(vec![path])).await?,
+                Action::KeepBoth => app.perform(app.keep_both(&path)).await?,
+                Action::Merge => match app.merge(&path).await {
+                    Some(Merge::Clean(merged)) => app.save_merge(path, merged).await?,
+                    Some(Merge::Conflicted(draft)) => {
+                        let file_name = path.rsplit('/').next().unwrap_or(&path);
+                        match edit_in_editor(terminal, file_name, &draft) {
+                            Ok(merged) => app.save_merge(path, merged).await?,
+                            Err(err) => app.report(err)?,
                         }
-                        _ => {}
-                    },
+                    }
+                    None => {}
                 },
+                Action::Back => app.return_to_file_actions(),
+                _ => {}
             }
         }
+        Mode::Search | Mode::SelectByStatus | Mode::ConfirmDelete(_) | Mode::NoFilesFound => {}
     }
+    Ok(false)
 }
@@ -0,0 +1,150 @@
+use std::{
+    io::Stderr,
+    time::{Duration, Instant},
+};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use ratatui::{backend::CrosstermBackend, layout::Position, prelude::Rect, Terminal};
+
+use super::{available_actions, handle_action, handle_fixed_key, keys::Action};
+use crate::{
+    cync::{Cync, Mode},
+    error::Error,
+};
+
+// Two clicks on the same row within this time count as a double click.
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+// Lines scrolled by one notch of the wheel in the diff pane.
+const SCROLL_LINES: isize = 3;
+
+/// What clicking a footer entry does: run an action, or press a key of a fixed-key mode.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Target {
+    Action(Action),
+    Key(KeyCode),
+}
+
+/// Where the clickable parts of the last frame were drawn.
+#[derive(Default)]
+pub struct ClickMap {
+    pub table: Option<Rect>,
+    pub footer: Vec<(Rect, Target)>,
+}
+
+impl ClickMap {
+    /// The tree row drawn at `position`, if any.
+    fn row_at(&self, app: &Cync, position: Position) -> Option<usize> {
+        let table = self.table?;
+        // The first line of the table is the header.
+        if !table.contains(position) || position.y == table.y {
+            return None;
+        }
+        let row = app.table_state.offset() + (position.y - table.y - 1) as usize;
+        (row < app.tree.len()).then_some(row)
+    }
+
+    fn target_at(&self, position: Position) -> Option<Target> {
+        self.footer
+            .iter()
+            .find(|(area, _)| area.contains(position))
+            .map(|(_, target)| *target)
+    }
+}
+
+#[derive(Default)]
+pub struct ClickTracker {
+    last: Option<(Instant, usize)>,
+}
+
+impl ClickTracker {
+    /// Records a click on `row` and returns whether it completes a double click.
+    fn click(&mut self, row: usize) -> bool {
+        let now = Instant::now();
+        let double = self
+            .last
+            .is_some_and(|(at, last_row)| last_row == row && now - at <= DOUBLE_CLICK);
+        self.last = if double { None } else { Some((now, row)) };
+        double
+    }
+}
+
+/// Handles a mouse event against the last frame. Returns whether to quit.
+pub async fn handle_mouse(
+    terminal: &mut Terminal<CrosstermBackend<Stderr>>,
+    app: &mut Cync,
+    clicks: &ClickMap,
+    tracker: &mut ClickTracker,
+    mouse: MouseEvent,
+) -> Result<bool, Error> {
+    if app.error_popup.is_some() {
+        return Ok(false);
+    }
+    if app.show_help {
+        if let MouseEventKind::Down(_) = mouse.kind {
+            app.show_help = false;
+        }
+        return Ok(false);
+    }
+
+    let position = Position::new(mouse.column, mouse.row);
+    let browsing = matches!(app.mode, Mode::Default | Mode::Visual(_) | Mode::Search);
+    match mouse.kind {
+        MouseEventKind::ScrollDown | MouseEventKind::ScrollUp => {
+            let down = mouse.kind == MouseEventKind::ScrollDown;
+            match (&mut app.diff, &app.mode) {
+                (Some(diff), Mode::Diff) => {
+                    diff.scroll_by(if down { SCROLL_LINES } else { -SCROLL_LINES })
+                }
+                (_, Mode::History) => {
+                    if let Some(history) = app.history.as_mut() {
+                        history.move_by(if down { 1 } else { -1 })
+                    }
+                }
+                (_, Mode::Trash) => {
+                    if let Some(trash) = app.trash.as_mut() {
+                        trash.move_by(if down { 1 } else { -1 })
+                    }
+                }
+                _ if browsing => app.move_cursor(if down { 1 } else { -1 }),
+                _ => {}
+            }
+        }
+        MouseEventKind::Down(MouseButton::Left) => {
+            if let Some(target) = clicks.target_at(position) {
+                return match target {
+                    Target::Action(action) if available_actions(app).contains(&action) => {
+                        handle_action(terminal, app, action).await
+                    }
+                    Target::Action(_) => Ok(false),
+                    Target::Key(code) => {
+                        let key = KeyEvent::new(code, KeyModifiers::NONE);
+                        Ok(handle_fixed_key(app, key).await?.unwrap_or_default())
+                    }
+                };
+            }
+            if let Some(row) = clicks.row_at(app, position).filter(|_| browsing) {
+                app.table_state.select(Some(row));
+                if tracker.click(row) && app.mode == Mode::Default {
+                    return handle_action(terminal, app, Action::Select).await;
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_double_click_needs_the_same_row() {
+        let mut tracker = ClickTracker::default();
+        assert!(!tracker.click(1));
+        assert!(!tracker.click(2));
+        assert!(tracker.click(2));
+        // A third click starts over instead of counting as another double click.
+        assert!(!tracker.click(2));
+    }
+}
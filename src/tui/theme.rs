@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+
+use ratatui::style::Color;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::cync::file_viewer::SyncState;
+
+/// A built-in set of colours to start from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Preset {
+    #[default]
+    Dark,
+    Light,
+    HighContrast,
+    ColourBlindSafe,
+}
+
+/// Something drawn in its own colour. The sync states colour the rows of the table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Element {
+    InSync,
+    LocalOnly,
+    RemoteOnly,
+    DeletedLocally,
+    DeletedRemotely,
+    ModifiedLocally,
+    ModifiedRemotely,
+    ModifiedOnBoth,
+    Differs,
+    /// Folders holding files that are out of sync.
+    OutOfSync,
+    Error,
+    Added,
+    Removed,
+    AddedBackground,
+    RemovedBackground,
+}
+
+impl From<SyncState> for Element {
+    fn from(state: SyncState) -> Self {
+        match state {
+            SyncState::InSync => Element::InSync,
+            SyncState::LocalOnly => Element::LocalOnly,
+            SyncState::RemoteOnly => Element::RemoteOnly,
+            SyncState::DeletedLocally => Element::DeletedLocally,
+            SyncState::DeletedRemotely => Element::DeletedRemotely,
+            SyncState::ModifiedLocally => Element::ModifiedLocally,
+            SyncState::ModifiedRemotely => Element::ModifiedRemotely,
+            SyncState::ModifiedOnBoth => Element::ModifiedOnBoth,
+            SyncState::Differs => Element::Differs,
+        }
+    }
+}
+
+/// The `[theme]` section of the config file. Colours are names such as `light-blue`, terminal
+/// palette indices or `#rrggbb`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThemeConfig {
+    pub preset: Preset,
+    pub colors: HashMap<Element, String>,
+}
+
+#[derive(Error, Debug)]
+#[error("Unknown colour `{color}` for `{element:?}`")]
+pub struct InvalidColor {
+    color: String,
+    element: Element,
+}
+
+pub struct Theme {
+    colors: HashMap<Element, Color>,
+    syntax: Option<&'static str>,
+}
+
+impl Preset {
+    fn colors(self) -> Vec<(Element, Color)> {
+        // Changes made locally, changes made on the remote, and changes made on both.
+        let (in_sync, local, remote, both, error, added, removed, added_bg, removed_bg) = match self
+        {
+            Preset::Dark => (
+                Color::Green,
+                Color::Yellow,
+                Color::LightBlue,
+                Color::LightRed,
+                Color::Red,
+                Color::Green,
+                Color::Red,
+                Color::Rgb(20, 60, 20),
+                Color::Rgb(70, 20, 20),
+            ),
+            Preset::Light => (
+                Color::Green,
+                Color::Blue,
+                Color::Magenta,
+                Color::Red,
+                Color::Red,
+                Color::Green,
+                Color::Red,
+                Color::Rgb(220, 245, 220),
+                Color::Rgb(250, 220, 220),
+            ),
+            Preset::HighContrast => (
+                Color::White,
+                Color::LightYellow,
+                Color::LightCyan,
+                Color::LightRed,
+                Color::LightRed,
+                Color::LightGreen,
+                Color::LightRed,
+                Color::Reset,
+                Color::Reset,
+            ),
+            // The Okabe-Ito palette, which stays apart under the common kinds of colour blindness.
+            Preset::ColourBlindSafe => (
+                Color::Rgb(0, 114, 178),
+                Color::Rgb(230, 159, 0),
+                Color::Rgb(204, 121, 167),
+                Color::Rgb(213, 94, 0),
+                Color::Rgb(213, 94, 0),
+                Color::Rgb(0, 114, 178),
+                Color::Rgb(230, 159, 0),
+                Color::Reset,
+                Color::Reset,
+            ),
+        };
+        vec![
+            (Element::InSync, in_sync),
+            (Element::LocalOnly, local),
+            (Element::DeletedLocally, local),
+            (Element::ModifiedLocally, local),
+            (Element::RemoteOnly, remote),
+            (Element::DeletedRemotely, remote),
+            (Element::ModifiedRemotely, remote),
+            (Element::ModifiedOnBoth, both),
+            (Element::Differs, both),
+            (Element::OutOfSync, local),
+            (Element::Error, error),
+            (Element::Added, added),
+            (Element::Removed, removed),
+            (Element::AddedBackground, added_bg),
+            (Element::RemovedBackground, removed_bg),
+        ]
+    }
+
+    /// The syntect theme used to highlight diffs.
+    fn syntax(self) -> &'static str {
+        match self {
+            Preset::Light => "InspiredGitHub",
+            _ => "base16-ocean.dark",
+        }
+    }
+}
+
+impl Theme {
+    /// With `no_color` set, the preset is ignored and only colours set in the config are used.
+    pub fn new(config: &ThemeConfig, no_color: bool) -> Result<Self, InvalidColor> {
+        let mut colors = if no_color {
+            HashMap::new()
+        } else {
+            config.preset.colors().into_iter().collect()
+        };
+        for (element, color) in &config.colors {
+            let parsed = color.parse().map_err(|_| InvalidColor {
+                color: color.clone(),
+                element: *element,
+            })?;
+            colors.insert(*element, parsed);
+        }
+        Ok(Theme {
+            colors,
+            syntax: (!no_color).then(|| config.preset.syntax()),
+        })
+    }
+
+    /// Whether `NO_COLOR` is set to anything but an empty string, see <https://no-color.org>.
+    pub fn no_color() -> bool {
+        std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty())
+    }
+
+    pub fn color(&self, element: Element) -> Color {
+        self.colors.get(&element).copied().unwrap_or(Color::Reset)
+    }
+
+    /// A background colour, or `None` when it should be left alone.
+    pub fn background(&self, element: Element) -> Option<Color> {
+        Some(self.color(element)).filter(|color| *color != Color::Reset)
+    }
+
+    /// The syntect theme for highlighting, or `None` to leave text uncoloured.
+    pub fn syntax(&self) -> Option<&'static str> {
+        self.syntax
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_color_keeps_only_configured_colours() {
+        let config = ThemeConfig {
+            preset: Preset::Dark,
+            colors: HashMap::from([(Element::Differs, String::from("#ff8800"))]),
+        };
+        let theme = Theme::new(&config, true).unwrap();
+        assert_eq!(theme.color(Element::Differs), Color::Rgb(255, 136, 0));
+        assert_eq!(theme.color(Element::InSync), Color::Reset);
+        assert_eq!(theme.syntax(), None);
+
+        let config = ThemeConfig {
+            colors: HashMap::from([(Element::Error, String::from("not a colour"))]),
+            ..config
+        };
+        assert!(Theme::new(&config, false).is_err());
+    }
+}
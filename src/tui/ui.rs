@@ -1,15 +1,33 @@
-use crate::cync::{file_viewer::FileKind, Cync, Mode};
+use super::{
+    available_actions, fixed_keys,
+    keys::{Action, KeyBindings},
+    mouse::{ClickMap, Target},
+    theme::Element,
+};
+use crate::{
+    cync::{
+        columns::Column, diff::DiffView, history::History, trash::TrashView, tree::TreeNode, Cync,
+        ErrorPopup, Mode,
+    },
+    error::Report,
+    util::{format_size, format_time},
+};
+use crossterm::event::KeyCode;
 use ratatui::{
-    prelude::{Alignment, Constraint, Rect},
-    style::{Color, Modifier, Style, Stylize},
-    text::Text,
-    widgets::{Block, Borders, Cell, HighlightSpacing, Paragraph, Row, Table},
+    prelude::{Alignment, Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style, Stylize},
+    text::{Line, Text},
+    widgets::{Block, Borders, Cell, Clear, HighlightSpacing, Paragraph, Row, Table, Wrap},
     Frame,
 };
+use std::fmt;
+use unicode_width::UnicodeWidthStr;
 
 const  NO_FILE_FOUND_TEXT: &str = "No files found in either local or remote directory. Please add files to either directory and restart Cync. Press any key to exit";
 
-pub fn ui(frame: &mut Frame, app: &mut Cync) {
+/// Draws the whole screen and returns where its clickable parts ended up.
+pub fn ui(frame: &mut Frame, app: &mut Cync) -> ClickMap {
+    let mut clicks = ClickMap::default();
     let area = frame.size();
     let block = Block::default()
         .title_top("Cync".bold())
@@ -18,106 +36,632 @@ pub fn ui(frame: &mut Frame, app: &mut Cync) {
     let block_inner = block.inner(area);
     frame.render_widget(block, area);
 
+    app.page_size = block_inner.height.saturating_sub(2).max(1) as usize;
+
     // Paragraph::new()
     if app.mode == Mode::NoFilesFound {
         frame.render_widget(Paragraph::new(NO_FILE_FOUND_TEXT).centered(), block_inner);
     } else {
-        render_table(frame, app, block_inner);
-        render_footer(frame, app, block_inner);
+        match (&app.diff, &mut app.history, &mut app.trash) {
+            (Some(diff), _, _) if app.mode == Mode::Diff => render_diff(frame, diff, block_inner),
+            (_, Some(history), _) if app.mode == Mode::History => {
+                render_history(frame, history, block_inner)
+            }
+            (_, _, Some(trash)) if app.mode == Mode::Trash => {
+                render_trash(frame, trash, block_inner)
+            }
+            _ if app.preview_open => {
+                let panes = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+                    .split(block_inner);
+                render_table(frame, app, panes[0]);
+                render_preview(frame, app, panes[1]);
+                clicks.table = Some(panes[0]);
+            }
+            _ => {
+                render_table(frame, app, block_inner);
+                clicks.table = Some(block_inner);
+            }
+        }
+        clicks.footer = render_footer(frame, app, block_inner);
     }
+
+    if app.show_help {
+        render_help(frame, app, area);
+    }
+    if let Some(popup) = &app.error_popup {
+        render_error_popup(frame, app, popup, area);
+    }
+    clicks
+}
+
+fn render_help(frame: &mut Frame, app: &Cync, area: Rect) {
+    let area = centered_rect(60, 70, area);
+    let keys = app.config.keys();
+    let mut bindings = available_actions(app)
+        .into_iter()
+        .map(|action| (keys.describe(action), action.description()))
+        .collect::<Vec<_>>();
+    bindings.extend(
+        fixed_keys(&app.mode)
+            .iter()
+            .map(|(key, description)| (key.to_string(), *description)),
+    );
+    let width = bindings
+        .iter()
+        .map(|(key, _)| UnicodeWidthStr::width(key.as_str()))
+        .max()
+        .unwrap_or_default();
+    let lines = bindings
+        .into_iter()
+        .map(|(key, description)| {
+            let padding = " ".repeat(width - UnicodeWidthStr::width(key.as_str()));
+            Line::from(format!("{padding}{key}  {description}"))
+        })
+        .collect::<Vec<_>>();
+
+    let block = Block::default()
+        .title_top("Help".bold())
+        .title_bottom("Press any key to close")
+        .title_alignment(Alignment::Center)
+        .borders(Borders::all());
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(block),
+        area,
+    );
+}
+
+fn render_error_popup(frame: &mut Frame, app: &Cync, popup: &ErrorPopup, area: Rect) {
+    let area = centered_rect(60, 40, area);
+    let actions = if popup.retry.is_some() {
+        "(r)etry, (q) dismiss"
+    } else {
+        "(q) dismiss"
+    };
+    let block = Block::default()
+        .title_top("Error".bold())
+        .title_bottom(actions)
+        .title_alignment(Alignment::Center)
+        .borders(Borders::all())
+        .fg(app.config.theme().color(Element::Error));
+    let text = Paragraph::new(Report(&popup.error).to_string())
+        .wrap(Wrap { trim: false })
+        .block(block);
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(text, area);
 }
 
-fn render_footer(frame: &mut Frame, app: &mut Cync, area: Rect) {
-    let text = match &app.mode {
-        Mode::Default => String::from("Up/Down: j/k, Select: <Enter>, Quit: q"),
-        Mode::PendingAction(kind) => match kind {
-            FileKind::OnlyInRemote { .. } => {
-                String::from("Select an action: Pull (f)rom remote, (Q)uit to previous screen")
+fn centered_rect(percent_x: u16, percent_y: u16, area: Rect) -> Rect {
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage((100 - percent_y) / 2),
+            Constraint::Percentage(percent_y),
+            Constraint::Percentage((100 - percent_y) / 2),
+        ])
+        .split(area);
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage((100 - percent_x) / 2),
+            Constraint::Percentage(percent_x),
+            Constraint::Percentage((100 - percent_x) / 2),
+        ])
+        .split(vertical[1])[1]
+}
+
+/// A piece of footer text, with what clicking it does.
+type Span = (String, Option<Target>);
+
+fn text(text: impl Into<String>) -> Span {
+    (text.into(), None)
+}
+
+fn action(text: impl Into<String>, action: Action) -> Span {
+    (text.into(), Some(Target::Action(action)))
+}
+
+fn key(text: impl Into<String>, code: char) -> Span {
+    (text.into(), Some(Target::Key(KeyCode::Char(code))))
+}
+
+/// A clickable hint for `action`, naming the first key bound to it.
+fn hint(keys: &KeyBindings, label: impl fmt::Display, action: Action) -> Span {
+    (
+        format!("{label}: {}", keys.primary(action)),
+        Some(Target::Action(action)),
+    )
+}
+
+/// How to move the cursor, as `Up/Down: j/k`.
+fn up_down(keys: &KeyBindings) -> String {
+    format!(
+        "Up/Down: {}/{}",
+        keys.primary(Action::Down),
+        keys.primary(Action::Up)
+    )
+}
+
+fn footer(app: &Cync) -> Vec<Span> {
+    let keys = app.config.keys();
+    match &app.mode {
+        Mode::Default => {
+            let throttle = app.config.aws_client().throttle();
+            let mut spans = vec![
+                text(format!("{}, ", up_down(keys))),
+                action(
+                    format!("Select: {}", keys.primary(Action::Select)),
+                    Action::Select,
+                ),
+                text(", "),
+                action(
+                    format!("Search: {}", keys.primary(Action::Search)),
+                    Action::Search,
+                ),
+                text(", "),
+            ];
+            match (throttle.is_configured(), throttle.is_enabled()) {
+                (false, _) => {}
+                (true, enabled) => spans.extend([
+                    action(
+                        format!(
+                            "{}: {}",
+                            if enabled { "Full speed" } else { "Throttle" },
+                            keys.primary(Action::Throttle)
+                        ),
+                        Action::Throttle,
+                    ),
+                    text(", "),
+                ]),
+            }
+            spans.extend([
+                action(
+                    format!("Help: {}", keys.primary(Action::Help)),
+                    Action::Help,
+                ),
+                text(", "),
+                action(
+                    format!("Quit: {}", keys.primary(Action::Quit)),
+                    Action::Quit,
+                ),
+            ]);
+            spans
+        }
+        Mode::Search => vec![
+            text(format!("Search: /{}_  ", app.filter.query)),
+            (
+                String::from("Accept: <Enter>"),
+                Some(Target::Key(KeyCode::Enter)),
+            ),
+            text(", "),
+            (
+                String::from("Cancel: <Esc>"),
+                Some(Target::Key(KeyCode::Esc)),
+            ),
+        ],
+        Mode::PendingAction(kind) => {
+            let mut spans = vec![text("Select an action: ")];
+            match (kind.can_push(), kind.can_pull()) {
+                (true, true) => spans.extend([
+                    hint(keys, "Push to remote", Action::Push),
+                    text(" / "),
+                    hint(keys, "Pull from remote", Action::Pull),
+                    text(", "),
+                    hint(keys, "Resolve", Action::Resolve),
+                    text(", "),
+                    hint(keys, "Compare", Action::Compare),
+                    text(", "),
+                    hint(keys, "History", Action::History),
+                    text(", "),
+                    hint(keys, "Delete", Action::Delete),
+                    text(", "),
+                    hint(keys, "Back", Action::Back),
+                ]),
+                (push, pull) => {
+                    if push {
+                        spans.extend([hint(keys, "Push to remote", Action::Push), text(", ")]);
+                    }
+                    if pull {
+                        spans.extend([hint(keys, "Pull from remote", Action::Pull), text(", ")]);
+                    }
+                    spans.extend([
+                        hint(keys, "History", Action::History),
+                        text(", "),
+                        hint(keys, "Delete", Action::Delete),
+                        text(", "),
+                        hint(keys, "Back to previous screen", Action::Back),
+                    ]);
+                }
             }
-            FileKind::OnlyInLocal { .. } => {
-                String::from("Select an action: Push (t)o remote, (q)uit to previous screen")
+            spans
+        }
+        Mode::PendingFolderAction(folder) => {
+            let (push, pull) = app.folder_actions(folder);
+            action_menu(keys, push.len(), pull.len())
+        }
+        Mode::PendingBatchAction => {
+            let (push, pull) = app.batch_actions();
+            let mut spans = vec![text(format!("{} marked. ", app.marked.len()))];
+            spans.extend(action_menu(keys, push.len(), pull.len()));
+            spans
+        }
+        Mode::Visual(_) => vec![
+            text(format!("Visual: {}, ", up_down(keys))),
+            hint(keys, "Mark range", Action::Visual),
+            text(", "),
+            hint(keys, "Cancel", Action::Back),
+        ],
+        Mode::SelectByStatus => vec![
+            text("Mark: "),
+            key("(a)ll", 'a'),
+            text(", "),
+            key("(o)ut of sync", 'o'),
+            text(", "),
+            key("(l)ocal only", 'l'),
+            text(", "),
+            key("(r)emote only", 'r'),
+            text(", "),
+            key("(c)hanged", 'c'),
+            text(", "),
+            key("(n)one", 'n'),
+            text(", "),
+            key("(q)uit", 'q'),
+        ],
+        Mode::ConfirmDelete(paths) => vec![
+            text(format!(
+                "Delete {} file(s) locally and on the remote? ",
+                paths.len()
+            )),
+            key("(y)es", 'y'),
+            text(" / "),
+            key("(n)o", 'n'),
+        ],
+        Mode::Diff => {
+            let layout = match &app.diff {
+                Some(diff) if diff.side_by_side => "Unified",
+                _ => "Side by side",
+            };
+            vec![
+                text(format!(
+                    "Scroll: {}/{}, {}/{}, ",
+                    keys.primary(Action::Down),
+                    keys.primary(Action::Up),
+                    keys.primary(Action::PageDown),
+                    keys.primary(Action::PageUp)
+                )),
+                hint(keys, layout, Action::DiffLayout),
+                text(", "),
+                hint(keys, "Back", Action::Back),
+            ]
+        }
+        Mode::ResolveConflict { can_merge } => {
+            let mut spans = vec![
+                text("Resolve conflict: "),
+                hint(keys, "Keep local", Action::KeepLocal),
+                text(", "),
+                hint(keys, "keep remote", Action::KeepRemote),
+                text(", "),
+                hint(keys, "keep both", Action::KeepBoth),
+                text(", "),
+            ];
+            if *can_merge {
+                spans.extend([hint(keys, "Merge", Action::Merge), text(", ")]);
             }
-            FileKind::ExistsInBoth {
-                local_hash,
-                remote_hash,
-                ..
-            } => {
-                if local_hash != remote_hash {
-                    String::from("Select an action: Push (t)o remote / Pull (f)rom remote")
+            spans.push(hint(keys, "Back", Action::Back));
+            spans
+        }
+        Mode::History => {
+            let version = app.history.as_ref().and_then(History::selected);
+            let mut spans = vec![text(format!("History: {}, ", up_down(keys)))];
+            if version.is_some_and(|version| version.can_restore()) {
+                let label = if version.is_some_and(|version| version.delete_marker) {
+                    "Restore deleted file"
                 } else {
-                    String::from("No actions availabble. Press (q) to quit")
-                }
+                    "Restore this version"
+                };
+                spans.extend([hint(keys, label, Action::Restore), text(", ")]);
+            }
+            if version.is_some_and(|version| version.can_download()) {
+                spans.extend([hint(keys, "Download from remote", Action::Pull), text(", ")]);
             }
-        },
+            spans.push(hint(keys, "Back", Action::Back));
+            spans
+        }
+        Mode::Trash => {
+            let mut spans = vec![text(format!("Trash: {}, ", up_down(keys)))];
+            if app.trash.as_ref().and_then(TrashView::selected).is_some() {
+                spans.extend([hint(keys, "Restore file", Action::Restore), text(", ")]);
+            }
+            spans.push(hint(keys, "Back", Action::Back));
+            spans
+        }
         Mode::NoFilesFound => unreachable!(),
-    };
+    }
+}
+
+/// Draws the footer and returns where its clickable spans ended up.
+fn render_footer(frame: &mut Frame, app: &mut Cync, area: Rect) -> Vec<(Rect, Target)> {
+    let spans = footer(app);
+    let text = spans
+        .iter()
+        .map(|(text, _)| text.as_str())
+        .collect::<String>();
+
+    // Centered the same way as the block title below.
+    let row = area.bottom().saturating_sub(1);
+    let mut x = area.x
+        + area
+            .width
+            .saturating_sub(UnicodeWidthStr::width(text.as_str()) as u16)
+            / 2;
+    let mut targets = Vec::new();
+    for (text, target) in &spans {
+        let width = UnicodeWidthStr::width(text.as_str()) as u16;
+        if let Some(target) = target {
+            let span = Rect::new(x, row, width, 1).intersection(area);
+            targets.push((span, *target));
+        }
+        x = x.saturating_add(width);
+    }
 
-    let block = Block::new()
+    let mut block = Block::new()
         .title_bottom(text)
         .title_alignment(Alignment::Center);
+    if app.filter.is_active() {
+        block = block.title_bottom(
+            Line::from(format!("Filter: {}", app.filter.describe())).alignment(Alignment::Left),
+        );
+    }
 
     frame.render_widget(block, area);
+    targets
+}
+
+fn action_menu(keys: &KeyBindings, push: usize, pull: usize) -> Vec<Span> {
+    let mut spans = vec![text("Select an action: ")];
+    let push_hint = || hint(keys, format!("Push {push} file(s) to remote"), Action::Push);
+    let pull_hint = || {
+        hint(
+            keys,
+            format!("Pull {pull} file(s) from remote"),
+            Action::Pull,
+        )
+    };
+    match (push, pull) {
+        (0, 0) => {}
+        (_, 0) => spans.extend([push_hint(), text(", ")]),
+        (0, _) => spans.extend([pull_hint(), text(", ")]),
+        _ => spans.extend([push_hint(), text(" / "), pull_hint(), text(", ")]),
+    }
+    spans.extend([
+        hint(keys, "Delete", Action::Delete),
+        text(", "),
+        hint(
+            keys,
+            if push > 0 && pull > 0 {
+                "Back"
+            } else {
+                "Back to previous screen"
+            },
+            Action::Back,
+        ),
+    ]);
+    spans
+}
+
+fn render_preview(frame: &mut Frame, app: &Cync, area: Rect) {
+    // Leave the last row for the footer.
+    let area = Rect {
+        height: area.height.saturating_sub(1),
+        ..area
+    };
+    let block = Block::new().borders(Borders::LEFT);
+    let paragraph = match &app.preview {
+        Some(preview) => Paragraph::new(preview.lines.clone())
+            .block(block.title_top(preview.path.clone()))
+            .wrap(Wrap { trim: false }),
+        None => Paragraph::new("Select a file to preview it").block(block),
+    };
+    frame.render_widget(paragraph, area);
+}
+
+fn render_diff(frame: &mut Frame, diff: &DiffView, area: Rect) {
+    // Leave the last row for the footer.
+    let area = Rect {
+        height: area.height.saturating_sub(1),
+        ..area
+    };
+    let title = format!("{} (remote → local)", diff.path);
+    let scroll = (diff.scroll.min(u16::MAX as usize) as u16, 0);
+
+    if diff.side_by_side && !diff.right.is_empty() {
+        let panes = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .split(area);
+        let remote = Paragraph::new(diff.left.clone())
+            .block(Block::new().borders(Borders::RIGHT).title_top("Remote"))
+            .scroll(scroll);
+        let local = Paragraph::new(diff.right.clone())
+            .block(Block::new().title_top(" Local"))
+            .scroll(scroll);
+        frame.render_widget(remote, panes[0]);
+        frame.render_widget(local, panes[1]);
+    } else {
+        let unified = Paragraph::new(diff.unified.clone())
+            .block(Block::new().title_top(title))
+            .scroll(scroll);
+        frame.render_widget(unified, area);
+    }
+}
+
+fn render_history(frame: &mut Frame, history: &mut History, area: Rect) {
+    // Leave the last row for the footer.
+    let area = Rect {
+        height: area.height.saturating_sub(1),
+        ..area
+    };
+    let block = Block::new().title_top(history.title.clone());
+    if history.versions.is_empty() {
+        let message = if history.deleted_files {
+            "No deleted files. Deleted files are only kept when versioning is enabled"
+        } else {
+            "No versions found"
+        };
+        frame.render_widget(Paragraph::new(message).centered().block(block), area);
+        return;
+    }
+
+    let rows = history.versions.iter().map(|version| {
+        let mut cells = Vec::new();
+        if history.deleted_files {
+            cells.push(version.path.clone());
+        }
+        cells.push(version.modified.map(format_time).unwrap_or_default());
+        cells.push(version.size.map(format_size).unwrap_or_default());
+        cells.push(
+            match (version.delete_marker, version.is_latest) {
+                (true, true) => "deleted",
+                (true, false) => "deleted, then restored",
+                (false, true) => "current",
+                (false, false) => "",
+            }
+            .to_string(),
+        );
+        Row::new(cells)
+    });
+    let mut constraints = Vec::new();
+    if history.deleted_files {
+        constraints.push(Constraint::Fill(1));
+    }
+    constraints.extend([
+        Constraint::Length(17),
+        Constraint::Length(11),
+        Constraint::Fill(1),
+    ]);
+    let table = Table::new(rows, constraints)
+        .block(block)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .highlight_spacing(HighlightSpacing::Always);
+    frame.render_stateful_widget(table, area, &mut history.state);
+}
+
+fn render_trash(frame: &mut Frame, trash: &mut TrashView, area: Rect) {
+    // Leave the last row for the footer.
+    let area = Rect {
+        height: area.height.saturating_sub(1),
+        ..area
+    };
+    let block = Block::new().title_top("Trash");
+    if trash.entries.is_empty() {
+        let message = "The trash is empty. Files overwritten or deleted locally end up here";
+        frame.render_widget(Paragraph::new(message).centered().block(block), area);
+        return;
+    }
+
+    let rows = trash.entries.iter().map(|entry| {
+        Row::new([
+            entry.path.clone(),
+            entry.trashed_at.format("%Y-%m-%d %H:%M").to_string(),
+            format_size(entry.size),
+        ])
+    });
+    let constraints = [
+        Constraint::Fill(1),
+        Constraint::Length(17),
+        Constraint::Length(11),
+    ];
+    let table = Table::new(rows, constraints)
+        .block(block)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .highlight_spacing(HighlightSpacing::Always);
+    frame.render_stateful_widget(table, area, &mut trash.state);
 }
 
 fn render_table(frame: &mut Frame, app: &mut Cync, area: Rect) {
+    if app.tree.is_empty() {
+        let clear = app.config.keys().primary(Action::ClearFilter);
+        frame.render_widget(
+            Paragraph::new(format!(
+                "No files match the current filter. Press {clear} to clear it"
+            ))
+            .centered(),
+            area,
+        );
+        return;
+    }
+
     let header_style = Style::default();
     let selected_style = Style::default().add_modifier(Modifier::REVERSED);
-    let header = ["Path", "Local Hash", "Remote Hash"]
-        .into_iter()
-        .map(Cell::from)
+    let header = app
+        .columns
+        .iter()
+        .map(|column| match app.sort {
+            Some(sort) if sort.column == *column => {
+                let arrow = if sort.descending { "▼" } else { "▲" };
+                Cell::from(format!("{} {arrow}", column.title()))
+            }
+            _ => Cell::from(column.title()),
+        })
         .collect::<Row>()
         .style(header_style)
         .height(1);
 
     let files = app.view_files();
-    let rows = files.iter().map(|(path, kind)| match kind {
-        FileKind::OnlyInRemote { hash, .. } => Row::new(vec![
-            Cell::from(path.to_owned()),
-            String::new().into(),
-            format!("{:?}", &hash).into(),
-        ])
-        .fg(Color::Yellow),
-        FileKind::OnlyInLocal { hash, .. } => Row::new(vec![
-            Cell::from(path.to_owned()),
-            format!("{:?}", &hash).into(),
-            String::new().into(),
-        ])
-        .fg(Color::Yellow),
-        FileKind::ExistsInBoth {
-            local_hash,
-            remote_hash,
-            ..
-        } => Row::new(vec![
-            Cell::from(path.to_owned()),
-            Cell::from(format!("{:?}", &local_hash)),
-            Cell::from(format!("{:?}", &remote_hash)),
-        ])
-        .fg(if local_hash == remote_hash {
-            Color::Green
+    let details = &app.files.1;
+    let marked = app.marked_files();
+    let theme = app.config.theme();
+    let rows = app.tree.iter().enumerate().map(|(index, node)| {
+        let covered = app.files_at(index);
+        let marker = if covered.is_empty() || !covered.iter().any(|path| marked.contains(path)) {
+            "  "
+        } else if covered.iter().all(|path| marked.contains(path)) {
+            "+ "
         } else {
-            Color::Yellow
-        }),
+            "~ "
+        };
+        let cells = app.columns.iter().map(|column| match (column, node) {
+            (Column::Path, node) => Cell::from(format!("{marker}{}", node.label())),
+            (_, TreeNode::Folder { .. }) => Cell::default(),
+            (column, TreeNode::File { path, .. }) => {
+                Cell::from(column.cell(path, &files[path], details.get(path)))
+            }
+        });
+        let element = match node {
+            TreeNode::Folder { out_of_sync, .. } if *out_of_sync == 0 => Element::InSync,
+            TreeNode::Folder { .. } => Element::OutOfSync,
+            TreeNode::File { path, .. } => match details.get(path) {
+                Some(details) => details.state.into(),
+                None if files[path].is_in_sync() => Element::InSync,
+                None => Element::Differs,
+            },
+        };
+        Row::new(cells).fg(theme.color(element))
     });
 
-    let longest_item_lens = app.constraint_len_calculator();
+    let widths = app.constraint_len_calculator();
+    let last = widths.len() - 1;
+    let constraints = widths.iter().enumerate().map(|(i, width)| match i {
+        // + 1 is for padding, + 2 for the mark.
+        0 => Constraint::Length(width + 3),
+        i if i == last => Constraint::Min(*width),
+        _ => Constraint::Length(width + 1),
+    });
     let bar = " █ ";
-    let t = Table::new(
-        rows,
-        [
-            // + 1 is for padding.
-            Constraint::Length(longest_item_lens.0 + 1),
-            Constraint::Min(longest_item_lens.1 + 1),
-            Constraint::Min(longest_item_lens.2),
-        ],
-    )
-    .header(header)
-    .highlight_style(selected_style)
-    .highlight_symbol(Text::from(vec![
-        "".into(),
-        bar.into(),
-        bar.into(),
-        "".into(),
-    ]))
-    .highlight_spacing(HighlightSpacing::Always);
+    let t = Table::new(rows, constraints)
+        .header(header)
+        .highlight_style(selected_style)
+        .highlight_symbol(Text::from(vec![
+            "".into(),
+            bar.into(),
+            bar.into(),
+            "".into(),
+        ]))
+        .highlight_spacing(HighlightSpacing::Always);
     frame.render_stateful_widget(t, area, &mut app.table_state);
 }
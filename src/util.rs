@@ -1,87 +1,291 @@
 use std::{
+    backtrace::Backtrace,
     collections::HashMap,
-    fs::{self, DirEntry, File},
-    io::{Read, Stderr},
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Stderr, Write},
+    os::unix::fs::OpenOptionsExt,
     path::PathBuf,
+    process::Command,
+    sync::atomic::{AtomicBool, Ordering},
+    time::SystemTime,
 };
 
 use crate::{
-    cync::{FileMetaData, FilePath},
-    error::{self, Error, LoadingLocalFiles, TuiErrorKind},
+    cync::{
+        file_viewer::{LocalContents, LocalFile},
+        hash_cache::HashCache,
+        trash::TRASH_DIRECTORY,
+        FilePath,
+    },
+    error::{Error, TuiErrorKind},
+    logging::{get_data_dir, LOG_FILE},
 };
+use chrono::{DateTime, Local};
+use crossterm::cursor::Show;
 use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
 use crossterm::execute;
 use crossterm::terminal::{
     disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
 };
 use ratatui::{prelude::CrosstermBackend, Terminal};
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{error, info};
+
+// Whether the TUI captures the mouse, so resuming from the editor restores the same state.
+static MOUSE_CAPTURE: AtomicBool = AtomicBool::new(true);
+
+/// `bytes` in the largest binary unit that keeps the value at or above one, e.g. `1.5 KiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+pub fn format_time(time: SystemTime) -> String {
+    DateTime::<Local>::from(time)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
 
 // TODO: Make more readable
 pub fn walk_directory(
     path: &PathBuf,
     top_level_path: &PathBuf,
-) -> Result<HashMap<FilePath, FileMetaData>, Error> {
+    hashes: &mut HashCache,
+) -> Result<HashMap<FilePath, LocalFile>, Error> {
     let mut result = HashMap::new();
-    for entry in
-        fs::read_dir(path).map_err(|_| Error::LoadingLocalFiles(LoadingLocalFiles::FileSystem))?
-    {
-        let entry = entry.map_err(|_| Error::LoadingLocalFiles(LoadingLocalFiles::FileSystem))?;
-        if entry.path().is_dir() {
-            if let Ok(next_level) = walk_directory(&entry.path(), top_level_path) {
-                result.extend(next_level);
-            } else {
-                return Err(Error::LoadingLocalFiles(LoadingLocalFiles::FileSystem));
-            }
-        } else {
-            let mut buf = Vec::new();
-            let _ = File::open(entry.path())
-                .map(|mut file| file.read_to_end(&mut buf))
-                .map_err(|_| Error::LocalFileCorrupted(get_path_from_entry(&entry)));
-            let file_hash = md5::compute(buf.clone());
-            if let Some(local_path) = get_path_from_entry(&entry)
-                .strip_prefix(&format!("{}/", top_level_path.as_path().display()))
-            {
-                result.insert(local_path.to_string(), (file_hash, buf));
-            } else {
-                panic!()
-            }
+    let loading_error = |source| Error::LoadingLocalFiles {
+        path: path.clone(),
+        source,
+    };
+    for entry in fs::read_dir(path).map_err(loading_error)? {
+        let entry = entry.map_err(loading_error)?;
+        if path == top_level_path && entry.file_name() == TRASH_DIRECTORY {
+            continue;
+        }
+        let entry_path = entry.path();
+        let metadata = fs::metadata(&entry_path).map_err(|source| Error::LocalFileCorrupted {
+            path: entry_path.clone(),
+            source,
+        })?;
+        if metadata.is_dir() {
+            result.extend(walk_directory(&entry_path, top_level_path, hashes)?);
+            continue;
         }
+        let local_path = entry_path
+            .strip_prefix(top_level_path)
+            .expect("walked entries are inside the top level directory")
+            .to_string_lossy()
+            .to_string();
+        // Files whose hash is cached are not opened at all, only stat'd.
+        let file = match hashes.reuse(&local_path, &metadata) {
+            Some(hash) => (hash, LocalContents::unread(entry_path, metadata.len())),
+            None => {
+                let mut buf = Vec::new();
+                // Stat'd after reading, so a write during the read changes the stamp that is
+                // cached.
+                let metadata = File::open(&entry_path)
+                    .and_then(|mut file| file.read_to_end(&mut buf).and_then(|_| file.metadata()))
+                    .map_err(|source| Error::LocalFileCorrupted {
+                        path: entry_path.clone(),
+                        source,
+                    })?;
+                let hash = hashes.hash(&local_path, &metadata, &buf);
+                (hash, LocalContents::loaded(entry_path, buf))
+            }
+        };
+        result.insert(local_path, file);
     }
 
     Ok(result)
 }
 
-pub fn get_path_from_entry(entry: &DirEntry) -> String {
-    entry
-        .path()
-        .as_path()
-        .to_str()
-        .expect("path to be utf-8")
-        .to_string()
-}
-
 pub fn initialize_terminal() -> Result<Terminal<CrosstermBackend<Stderr>>, Error> {
-    enable_raw_mode().map_err(|_| Error::Tui(TuiErrorKind::Initilization))?;
+    let tui_error = |source| Error::Tui {
+        kind: TuiErrorKind::Initialization,
+        source,
+    };
+    enable_raw_mode().map_err(tui_error)?;
     let mut stderr = std::io::stderr();
-    execute!(stderr, EnterAlternateScreen, EnableMouseCapture)
-        .map_err(|_| Error::Tui(error::TuiErrorKind::Initilization))?;
+    execute!(stderr, EnterAlternateScreen, EnableMouseCapture).map_err(tui_error)?;
     let backend = CrosstermBackend::new(stderr);
-    let terminal = Terminal::new(backend).map_err(|_| Error::Tui(TuiErrorKind::Initilization))?;
+    let terminal = Terminal::new(backend).map_err(tui_error)?;
     Ok(terminal)
 }
 
 pub fn restore_terminal(mut terminal: Terminal<CrosstermBackend<Stderr>>) -> Result<(), Error> {
-    disable_raw_mode().map_err(|_| Error::Tui(TuiErrorKind::TerminalRestoration))?;
+    reset_terminal()?;
+    terminal.show_cursor().map_err(|source| Error::Tui {
+        kind: TuiErrorKind::TerminalRestoration,
+        source,
+    })
+}
+
+/// Turns mouse capture on or off. With it off the terminal keeps its own text selection.
+pub fn set_mouse_capture(enabled: bool) -> Result<(), Error> {
+    MOUSE_CAPTURE.store(enabled, Ordering::Relaxed);
+    let result = if enabled {
+        execute!(std::io::stderr(), EnableMouseCapture)
+    } else {
+        execute!(std::io::stderr(), DisableMouseCapture)
+    };
+    result.map_err(|source| Error::Tui {
+        kind: TuiErrorKind::Initialization,
+        source,
+    })
+}
+
+/// Leaves raw mode and the alternate screen without needing the `Terminal` handle, so it
+/// can also be called from the panic hook and signal handlers.
+pub fn reset_terminal() -> Result<(), Error> {
+    let tui_error = |source| Error::Tui {
+        kind: TuiErrorKind::TerminalRestoration,
+        source,
+    };
+    disable_raw_mode().map_err(tui_error)?;
     execute!(
-        terminal.backend_mut(),
+        std::io::stderr(),
         LeaveAlternateScreen,
-        DisableMouseCapture
+        DisableMouseCapture,
+        Show
     )
-    .map_err(|_| Error::Tui(TuiErrorKind::TerminalRestoration))?;
+    .map_err(tui_error)
+}
+
+/// Re-enters the TUI after the terminal was handed over to another program.
+fn resume_terminal(terminal: &mut Terminal<CrosstermBackend<Stderr>>) -> Result<(), Error> {
+    let tui_error = |source| Error::Tui {
+        kind: TuiErrorKind::Initialization,
+        source,
+    };
+    enable_raw_mode().map_err(tui_error)?;
+    execute!(std::io::stderr(), EnterAlternateScreen).map_err(tui_error)?;
+    if MOUSE_CAPTURE.load(Ordering::Relaxed) {
+        execute!(std::io::stderr(), EnableMouseCapture).map_err(tui_error)?;
+    }
+    terminal.clear().map_err(tui_error)
+}
 
-    terminal
-        .show_cursor()
-        .map_err(|_| Error::Tui(TuiErrorKind::TerminalRestoration))?;
+/// Opens `contents` in `$VISUAL` or `$EDITOR` (falling back to `vi`) under `file_name`, and
+/// returns the text once the editor exits.
+pub fn edit_in_editor(
+    terminal: &mut Terminal<CrosstermBackend<Stderr>>,
+    file_name: &str,
+    contents: &str,
+) -> Result<String, Error> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| String::from("vi"));
+    // A private directory with a random name, so nobody else can plant a file or symlink where
+    // both sides of the conflict are written. It is removed when dropped, however this returns.
+    let directory = tempfile::Builder::new()
+        .prefix("cync-merge-")
+        .tempdir()
+        .map_err(|source| Error::MergeFile {
+            path: std::env::temp_dir(),
+            source,
+        })?;
+    let path = directory.path().join(file_name);
+    let merge_file_error = |source| Error::MergeFile {
+        path: path.clone(),
+        source,
+    };
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .map_err(merge_file_error)?;
+
+    reset_terminal()?;
+    // Going through the shell lets `$EDITOR` carry arguments, e.g. `code --wait`.
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{editor} \"$1\""))
+        .arg("sh")
+        .arg(&path)
+        .status();
+    resume_terminal(terminal)?;
+
+    let editor_error = |source| Error::Editor {
+        editor: editor.clone(),
+        source,
+    };
+    let status = status.map_err(editor_error)?;
+    if !status.success() {
+        return Err(editor_error(io::Error::other(format!(
+            "exited with {status}"
+        ))));
+    }
+    fs::read_to_string(&path).map_err(merge_file_error)
+}
 
-    Ok(())
+/// Restores the terminal before a panic message is printed, and sends the full panic with
+/// a backtrace to the log file rather than over the user's screen.
+pub fn initialize_panic_handler() {
+    std::panic::set_hook(Box::new(|panic_info| {
+        let _ = reset_terminal();
+        let backtrace = Backtrace::force_capture();
+        error!("{panic_info}\n{backtrace}");
+
+        let message = panic_info
+            .payload()
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| panic_info.payload().downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| String::from("unknown error"));
+        let location = panic_info
+            .location()
+            .map(|location| format!(" at {location}"))
+            .unwrap_or_default();
+        eprintln!(
+            "Cync crashed: {message}{location}\nThe full report was written to `{}`",
+            get_data_dir().join(LOG_FILE.clone()).display()
+        );
+    }));
+}
+
+/// Restores the terminal and exits when the process is asked to stop while the TUI is up. In
+/// raw mode Ctrl+C arrives as a key press instead, so SIGINT only comes from outside the TUI.
+pub fn handle_termination_signals() {
+    tokio::spawn(async {
+        let (Ok(mut interrupt), Ok(mut terminate)) = (
+            signal(SignalKind::interrupt()),
+            signal(SignalKind::terminate()),
+        ) else {
+            error!("Failed to install signal handlers");
+            return;
+        };
+
+        let code = tokio::select! {
+            _ = interrupt.recv() => 130,
+            _ = terminate.recv() => 143,
+        };
+        info!("Received termination signal, exiting");
+        let _ = reset_terminal();
+        std::process::exit(code);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(5 * 1024 * 1024), "5.0 MiB");
+    }
 }